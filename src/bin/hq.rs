@@ -1,10 +1,18 @@
 use std::{
     error::Error,
     fs,
-    io::{self, Read, Write},
+    io::{self, IsTerminal, Read, Write},
+    process::ExitCode,
 };
 
 use clap::{Parser, Subcommand};
+use hcl::format::Format;
+use hq_rs::{
+    filter::error::FilterError,
+    parser::{parse_filter_with_variables, Rule, Segment, Variables},
+    query::QueryResult,
+    Filter,
+};
 
 #[derive(Parser)]
 #[command(version, about)]
@@ -26,6 +34,31 @@ struct Args {
     )]
     file: Option<String>,
 
+    #[clap(
+        long = "diagnose",
+        help = "When the filter has no results (or a segment of it is missing), explain which segment of it matched nothing"
+    )]
+    diagnose: bool,
+
+    #[arg(
+        long = "arg",
+        num_args = 2,
+        value_names = ["NAME", "VALUE"],
+        global = true,
+        help = "Set the filter variable $NAME to the string VALUE"
+    )]
+    arg: Vec<String>,
+
+    #[arg(
+        long = "arg-hcl",
+        visible_alias = "argjson",
+        num_args = 2,
+        value_names = ["NAME", "EXPR"],
+        global = true,
+        help = "Set the filter variable $NAME to the value of the HCL (or JSON) expression EXPR"
+    )]
+    arg_hcl: Vec<String>,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -42,6 +75,12 @@ enum Command {
         )]
         file: Option<String>,
 
+        #[clap(
+            long = "diagnose",
+            help = "When the filter has no results (or a segment of it is missing), explain which segment of it matched nothing"
+        )]
+        diagnose: bool,
+
         #[arg(
             value_name = "FILTER",
             help = "HCL filter expression",
@@ -99,33 +138,114 @@ enum Command {
         )]
         filter: String,
     },
+    #[command(about = "Show what each segment of a filter matches in HCL")]
+    Explain {
+        #[clap(
+            short = 'f',
+            long = "file",
+            value_name = "FILE",
+            help = "HCL file to read from"
+        )]
+        file: Option<String>,
+
+        #[arg(
+            value_name = "FILTER",
+            help = "HCL filter expression",
+            long_help = "HCL filter expression\nsee https://docs.rs/hq-rs/latest/hq_rs/ for filter examples"
+        )]
+        filter: String,
+    },
+    #[command(about = "List the path of every attribute, block and value in HCL")]
+    Paths {
+        #[clap(
+            short = 'f',
+            long = "file",
+            value_name = "FILE",
+            help = "HCL file to read from"
+        )]
+        file: Option<String>,
+
+        #[clap(
+            long = "values",
+            help = "Show the value of each path that is not a block, an object or an array"
+        )]
+        values: bool,
+    },
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
     let args = Args::parse();
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            match err.downcast_ref::<Box<FilterError<Rule>>>() {
+                // a diagnostic is only colored when it is shown in a terminal
+                Some(err) => {
+                    let styled = io::stdout().is_terminal() && io::stderr().is_terminal();
+                    eprintln!("{}", err.render(styled));
+                }
+                // `{err:?}` would escape the quotes in a message
+                None => eprintln!("Error: {err}"),
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let variables = variables(&args.arg, &args.arg_hcl)?;
 
     match args.command {
         None => {
-            read(args.file, args.filter)?;
+            read(args.file, args.filter, args.diagnose, &variables)?;
         }
-        Some(Command::Read { file, filter }) => {
-            read(file, filter)?;
+        Some(Command::Read {
+            file,
+            diagnose,
+            filter,
+        }) => {
+            read(file, filter, diagnose, &variables)?;
         }
         Some(Command::Write { file, inline, expr }) => {
-            write(file, inline, expr)?;
+            write(file, inline, expr, &variables)?;
         }
         Some(Command::Delete {
             file,
             inline,
             filter,
         }) => {
-            delete(file, inline, filter)?;
+            delete(file, inline, filter, &variables)?;
+        }
+        Some(Command::Explain { file, filter }) => {
+            explain(file, filter, &variables)?;
+        }
+        Some(Command::Paths { file, values }) => {
+            paths(file, values)?;
         }
     }
 
     Ok(())
 }
 
+/// the filter variables of `--arg` (strings) and `--arg-hcl` (any value), which
+/// are both given as a flat list of names and values
+fn variables(args: &[String], hcl_args: &[String]) -> Result<Variables, Box<dyn Error>> {
+    let mut variables = Variables::new();
+    for pair in args.chunks_exact(2) {
+        variables.insert(pair[0].clone(), hcl::Value::from(pair[1].as_str()));
+    }
+    for pair in hcl_args.chunks_exact(2) {
+        let expr: hcl_edit::expr::Expression = pair[1]
+            .parse()
+            .map_err(|err| format!("invalid value for --arg-hcl {}: {err}", pair[0]))?;
+        variables.insert(
+            pair[0].clone(),
+            hcl::Value::from(hcl::Expression::from(expr)),
+        );
+    }
+    Ok(variables)
+}
+
 fn read_stdin() -> Result<String, Box<dyn Error>> {
     let mut stdin = io::stdin();
     let mut buf = String::new();
@@ -133,52 +253,59 @@ fn read_stdin() -> Result<String, Box<dyn Error>> {
     Ok(buf)
 }
 
-fn read(file: Option<String>, filter: Option<String>) -> Result<(), Box<dyn Error>> {
+fn read(
+    file: Option<String>,
+    filter: Option<String>,
+    diagnose: bool,
+    variables: &Variables,
+) -> Result<(), Box<dyn Error>> {
     let contents = match file {
         Some(file) => fs::read_to_string(file)?,
         None => read_stdin()?,
     };
     let body: hcl::Body = hcl::from_str(&contents)?;
-    match filter {
-        Some(filter) => {
-            let mut fields = hq_rs::parse_filter(&filter)?;
-            let query_results = hq_rs::query(&mut fields, &body);
-            for query_result in query_results {
-                let s = query_result.to_string()?;
-                print!("{s}");
-                io::stdout().flush()?;
-                if !s.ends_with('\n') {
-                    println!();
-                }
-            }
+    // without a filter the whole document is returned (the identity filter)
+    let filter = filter.unwrap_or_else(|| String::from("."));
+    let filter = parse_filter_with_variables(&filter, variables)?;
+    let query_results = hq_rs::query(&filter, &body);
+    // a missing field is an error, which is explained the same way as a
+    // filter without results
+    let matched_nothing = query_results.as_ref().map_or(true, Vec::is_empty);
+    if diagnose && matched_nothing {
+        for mismatch in hq_rs::query::diagnose(&filter, &body) {
+            eprintln!("note: {mismatch}");
         }
-        None => {
-            println!("HCL from stdin contained:");
-            println!(" * {} top-level attribute(s)", body.attributes().count());
-            println!(" * {} top-level block(s)", body.blocks().count());
+    }
+    let query_results = query_results?;
+    for query_result in query_results {
+        let s = query_result.to_string()?;
+        print!("{s}");
+        io::stdout().flush()?;
+        if !s.ends_with('\n') {
+            println!();
         }
     }
     Ok(())
 }
 
-fn write(file: Option<String>, inline: bool, expr: String) -> Result<(), Box<dyn Error>> {
+fn write(
+    file: Option<String>,
+    inline: bool,
+    expr: String,
+    variables: &Variables,
+) -> Result<(), Box<dyn Error>> {
     let contents = match file {
         Some(ref file) => fs::read_to_string(file)?,
         None => read_stdin()?,
     };
     let mut body: hcl_edit::structure::Body = contents.parse()?;
-    if !expr.contains('=') {
-        return Err("write expression should be <FILTER>=<VALUE>".into());
-    }
-    let parts: Vec<_> = expr.split('=').collect();
-    if parts.len() != 2 {
+    let Some((filter, new_value)) = split_write_expr(&expr) else {
         return Err("write expression should be <FILTER>=<VALUE>".into());
-    }
-    let filter = parts[0];
-    let new_value = parts[1];
-    let expr: hcl_edit::expr::Expression = new_value.parse()?;
-    let fields = hq_rs::parse_filter(filter)?;
-    hq_rs::write(fields, &mut body, &expr);
+    };
+    let filter = filter.trim();
+    let expr: hcl_edit::expr::Expression = new_value.trim().parse()?;
+    let filter = parse_filter_with_variables(filter, variables)?;
+    hq_rs::write(&filter, &mut body, &expr)?;
 
     if inline {
         // When inline is set, write the modified HCL back to the file
@@ -193,14 +320,55 @@ fn write(file: Option<String>, inline: bool, expr: String) -> Result<(), Box<dyn
     Ok(())
 }
 
-fn delete(file: Option<String>, inline: bool, filter: String) -> Result<(), Box<dyn Error>> {
+/// split a write expression `<FILTER>=<VALUE>` at its `=`, which is the first
+/// one that is not quoted, not inside brackets or parentheses (e.g. in a
+/// `select(...)` predicate) and not part of a comparison like `==` or `!=`
+fn split_write_expr(expr: &str) -> Option<(&str, &str)> {
+    let chars: Vec<_> = expr.char_indices().collect();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, &(pos, c)) in chars.iter().enumerate() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            '=' if depth == 0 => {
+                let previous = i.checked_sub(1).map(|j| chars[j].1);
+                let next = chars.get(i + 1).map(|&(_, c)| c);
+                if !matches!(previous, Some('=' | '!' | '<' | '>')) && next != Some('=') {
+                    return Some((&expr[..pos], &expr[pos + 1..]));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn delete(
+    file: Option<String>,
+    inline: bool,
+    filter: String,
+    variables: &Variables,
+) -> Result<(), Box<dyn Error>> {
     let contents = match file {
         Some(ref file) => fs::read_to_string(file)?,
         None => read_stdin()?,
     };
     let mut body: hcl_edit::structure::Body = contents.parse()?;
-    let fields = hq_rs::parse_filter(&filter)?;
-    hq_rs::delete(fields, &mut body)?;
+    let filter = parse_filter_with_variables(&filter, variables)?;
+    hq_rs::delete(&filter, &mut body)?;
 
     if inline {
         // When inline is set, write the modified HCL back to the file
@@ -214,3 +382,105 @@ fn delete(file: Option<String>, inline: bool, filter: String) -> Result<(), Box<
 
     Ok(())
 }
+
+fn explain(
+    file: Option<String>,
+    filter: String,
+    variables: &Variables,
+) -> Result<(), Box<dyn Error>> {
+    let contents = match file {
+        Some(file) => fs::read_to_string(file)?,
+        None => read_stdin()?,
+    };
+    // the document is parsed twice: once to query it, and once more to find
+    // where the results are in it
+    let body: hcl::Body = hcl::from_str(&contents)?;
+    let edit_body: hcl_edit::structure::Body = contents.parse()?;
+    let filter = parse_filter_with_variables(&filter, variables)?;
+    println!("filter: {filter}");
+    for line in filter.tree().lines() {
+        println!("  {line}");
+    }
+
+    for (index, step) in hq_rs::query::trace(&filter, &body)?.iter().enumerate() {
+        println!();
+        println!(
+            "{}. `{}`: {} (expressions: {}, blocks: {})",
+            index + 1,
+            step.filter,
+            plural(step.results.len(), "result"),
+            step.exprs(),
+            step.blocks()
+        );
+        // the n-th result at a location is at its n-th range, since more than
+        // one block can have the same identifier and labels
+        let mut seen: Vec<&[Segment]> = Vec::new();
+        for traced in &step.results {
+            let Some(location) = &traced.location else {
+                println!("   (computed)");
+                continue;
+            };
+            let nth = seen.iter().filter(|seen| **seen == location).count();
+            seen.push(location);
+            let path = Filter::Path(location.clone());
+            match hq_rs::query::locate(location, &edit_body).get(nth) {
+                Some(range) => {
+                    let (line, column) = line_column(&contents, range.start);
+                    println!("   {path} at {line}:{column}");
+                }
+                None => println!("   {path}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn paths(file: Option<String>, values: bool) -> Result<(), Box<dyn Error>> {
+    let contents = match file {
+        Some(file) => fs::read_to_string(file)?,
+        None => read_stdin()?,
+    };
+    let body: hcl::Body = hcl::from_str(&contents)?;
+    for (path, query_result) in hq_rs::query::paths(&body) {
+        let path = Filter::Path(path);
+        match query_result {
+            QueryResult::Expr(expr) if values && is_leaf(&expr) => {
+                // a value is on the same line as its path, to be found with `grep`
+                let mut formatter = hcl::format::Formatter::builder().compact(true).build_vec();
+                println!("{path} = {}", expr.format_string(&mut formatter)?);
+            }
+            _ => println!("{path}"),
+        }
+    }
+    Ok(())
+}
+
+/// whether `expr` is shown with its path by `hq paths --values`, which is
+/// anything but an object or array that has paths inside of it (a function
+/// call is shown although its arguments have paths too)
+fn is_leaf(expr: &hcl::Expression) -> bool {
+    match expr {
+        hcl::Expression::Object(object) => object.is_empty(),
+        hcl::Expression::Array(array) => array.is_empty(),
+        hcl::Expression::Parenthesis(inner) => is_leaf(inner),
+        _ => true,
+    }
+}
+
+/// e.g. "1 result" or "2 results"
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+/// the (1-based) line and column of the byte `offset` of `contents`
+fn line_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
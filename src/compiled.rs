@@ -0,0 +1,99 @@
+//! filters that are parsed once and then applied to any number of documents
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::{
+    filter::error::FilterError,
+    parser::{parse_filter_with_variables, Filter, Rule, Variables},
+    query::QueryResult,
+};
+
+/// a parsed [`Filter`] that can be applied to many documents, e.g. the same
+/// filter to every file of a directory
+///
+/// it never changes once it is parsed, and cloning it is cheap (clones share
+/// the filter), so it can be shared between threads
+///
+/// ```
+/// use hq_rs::CompiledFilter;
+///
+/// let filter: CompiledFilter = ".variable{\"my_var\"}.default".parse().unwrap();
+/// for contents in ["variable \"my_var\" { default = 1 }", "variable \"my_var\" { default = 2 }"] {
+///     let body = hcl::from_str(contents).unwrap();
+///     let results = filter.query(&body).unwrap();
+///     assert_eq!(1, results.len());
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledFilter {
+    filter: Arc<Filter>,
+}
+
+impl CompiledFilter {
+    /// parse `input` (see [`parse_filter`][crate::parse_filter])
+    pub fn new(input: &str) -> Result<Self, Box<FilterError<Rule>>> {
+        Self::with_variables(input, &Variables::new())
+    }
+
+    /// parse `input` with the values of its variables (see
+    /// [`parse_filter_with_variables`])
+    pub fn with_variables(
+        input: &str,
+        variables: &Variables,
+    ) -> Result<Self, Box<FilterError<Rule>>> {
+        Ok(Self::from(parse_filter_with_variables(input, variables)?))
+    }
+
+    /// the parsed filter
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    /// the results of the filter in `body` (see [`query`][crate::query()])
+    pub fn query(&self, body: &hcl::Body) -> Result<Vec<QueryResult>, Box<dyn Error>> {
+        crate::query(&self.filter, body)
+    }
+
+    /// write `value` to what the filter matches in `body` (see
+    /// [`write`][crate::write()])
+    pub fn write(
+        &self,
+        body: &mut hcl_edit::structure::Body,
+        value: &hcl_edit::expr::Expression,
+    ) -> Result<(), Box<dyn Error>> {
+        crate::write(&self.filter, body, value)
+    }
+
+    /// remove what the filter matches from `body` (see
+    /// [`delete`][crate::delete()])
+    pub fn delete(&self, body: &mut hcl_edit::structure::Body) -> Result<(), Box<dyn Error>> {
+        crate::delete(&self.filter, body)
+    }
+}
+
+impl From<Filter> for CompiledFilter {
+    fn from(filter: Filter) -> Self {
+        CompiledFilter {
+            filter: Arc::new(filter),
+        }
+    }
+}
+
+impl FromStr for CompiledFilter {
+    type Err = Box<FilterError<Rule>>;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::new(input)
+    }
+}
+
+impl Display for CompiledFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.filter.fmt(f)
+    }
+}
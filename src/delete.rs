@@ -3,129 +3,215 @@
 use std::error::Error;
 
 use hcl_edit::{
+    expr::{Array, Expression, FuncArgs},
     structure::{Body, Structure},
     visit_mut::VisitMut,
+    Decorate,
 };
 
-use crate::parser::Field;
+use crate::{
+    parser::{resolve_index, resolve_slice, without_optional, Filter, Segment},
+    parts::{edit_parts_mut, expr_result, object_key_name, slice_result, Cursor, Part},
+};
 
 struct HclDeleter {
-    fields: Vec<Field>,
-    current_index: usize,
-    current: Option<Field>,
-    error: Option<Box<dyn Error>>,
+    cursor: Cursor,
 }
 
 impl HclDeleter {
-    fn new(fields: Vec<Field>) -> Self {
-        let current = fields.first().cloned();
-        HclDeleter {
-            fields,
-            current_index: 0,
-            current,
-            error: None,
+    /// remove the arguments of a function call that the current segment
+    /// selects
+    fn remove_args(&mut self, args: &mut FuncArgs) {
+        let Some(current) = self.cursor.current() else {
+            return;
+        };
+        let count = args.len();
+        let selected: Vec<_> = args
+            .iter()
+            .enumerate()
+            .filter(|(index, arg)| {
+                Part::Index(*index).matches(&current, count)
+                    && self.cursor.selects(|| expr_result(arg))
+            })
+            .map(|(index, _)| index)
+            .collect();
+        if selected.is_empty() {
+            self.cursor.missing();
+        }
+        for index in selected.into_iter().rev() {
+            let removed = args.remove(index);
+            if index == 0 {
+                keep_prefix(&removed, args.get_mut(0));
+            }
         }
-    }
-
-    fn next_field(&mut self) {
-        self.current_index += 1;
-        self.current = self.fields.get(self.current_index).cloned();
-    }
-
-    fn previous_field(&mut self) {
-        self.current_index -= 1;
-        self.current = self.fields.get(self.current_index).cloned();
-    }
-
-    fn should_remove(&self) -> bool {
-        self.current_index >= self.fields.len() - 1
     }
 }
 
 impl VisitMut for HclDeleter {
     fn visit_body_mut(&mut self, node: &mut Body) {
-        if let Some(current) = self.current.clone() {
-            let mut matching_attr_keys = Vec::new();
-            let mut matching_block_idents = Vec::new();
-            for item in node.iter() {
-                match item {
-                    Structure::Attribute(attr) => {
-                        if attr.key.as_str() == current.name {
-                            matching_attr_keys.push(attr.key.to_string());
-                        }
+        if self.cursor.current().is_none() {
+            return;
+        }
+        let mut matches = Vec::new();
+        for (index, item) in node.iter().enumerate() {
+            let steps = match item {
+                Structure::Attribute(attr) => self.cursor.selects_attr(attr).then_some(0),
+                Structure::Block(block) => self.cursor.selects_block(block),
+            };
+            if let Some(steps) = steps {
+                matches.push((index, steps));
+            }
+        }
+        if matches.is_empty() {
+            self.cursor.missing();
+        }
+
+        let mut removed = Vec::new();
+        for (index, steps) in matches {
+            // `.*` can step over the labels of a block (e.g. `.resource.*` is
+            // every resource block), which leaves the block itself to remove
+            self.cursor.skip(steps);
+            if self.cursor.is_last() {
+                removed.push(index);
+            } else {
+                // Index was gotten iterating over the node, so it must be a non-None value.
+                let structure = node.get_mut(index).unwrap();
+                self.cursor.next_segment();
+                match structure {
+                    Structure::Attribute(attr) => self.visit_expr_mut(&mut attr.value),
+                    Structure::Block(block) => self.visit_block_mut(block),
+                }
+                self.cursor.previous_segment();
+            }
+            self.cursor.unskip(steps);
+        }
+        // remove from the end so that the remaining indexes stay valid
+        for index in removed.into_iter().rev() {
+            node.remove(index);
+        }
+    }
+
+    fn visit_expr_mut(&mut self, node: &mut Expression) {
+        match node {
+            Expression::Array(array) => self.visit_array_mut(array),
+            Expression::Object(object) => self.visit_object_mut(object),
+            Expression::Parenthesis(parenthesis) => self.visit_expr_mut(parenthesis.inner_mut()),
+            // arguments are removed like the elements of an array
+            Expression::FuncCall(call) if self.cursor.is_last() => self.remove_args(&mut call.args),
+            // e.g. the branches of a conditional
+            node => {
+                let Some(current) = self.cursor.current() else {
+                    return;
+                };
+                let parts = edit_parts_mut(node);
+                let count = parts.len();
+                let mut matched = false;
+                for (part, expr) in parts {
+                    if !part.matches(&current, count) || !self.cursor.selects(|| expr_result(expr))
+                    {
+                        continue;
                     }
-                    Structure::Block(block) => {
-                        if block.ident.as_str() == current.name {
-                            if current.labels.is_empty() {
-                                matching_block_idents.push(block.ident.to_string());
-                            } else {
-                                for filter_label in &current.labels {
-                                    for block_label in &block.labels {
-                                        if block_label.as_str() == filter_label {
-                                            matching_block_idents.push(block.ident.to_string());
-                                        }
-                                    }
-                                }
-                            }
-                        }
+                    matched = true;
+                    if self.cursor.is_last() {
+                        self.cursor.fail(format!(
+                            "`{current}` is a part of an expression that cannot be removed"
+                        ));
+                        return;
                     }
+                    self.cursor.next_segment();
+                    self.visit_expr_mut(expr);
+                    self.cursor.previous_segment();
+                }
+                if !matched {
+                    self.cursor.missing();
                 }
             }
+        }
+    }
 
-            for key in matching_attr_keys {
-                if self.should_remove() {
-                    node.remove_attribute(&key);
+    fn visit_array_mut(&mut self, node: &mut Array) {
+        match self.cursor.current() {
+            Some(Segment::Index(index)) => {
+                let index = resolve_index(index, node.len()).filter(|index| {
+                    node.get(*index)
+                        .is_some_and(|element| self.cursor.selects(|| expr_result(element)))
+                });
+                let Some(index) = index else {
+                    self.cursor.missing();
+                    return;
+                };
+                if self.cursor.is_last() {
+                    remove_element(node, index);
+                } else if let Some(element) = node.get_mut(index) {
+                    self.cursor.next_segment();
+                    self.visit_expr_mut(element);
+                    self.cursor.previous_segment();
+                }
+            }
+            Some(Segment::Slice(start, end)) => {
+                let range = resolve_slice(start, end, node.len());
+                if !self.cursor.selects(|| slice_result(node, range.clone())) {
+                    return;
+                }
+                if self.cursor.is_last() {
+                    for index in range.rev() {
+                        remove_element(node, index);
+                    }
                 } else {
-                    self.next_field();
-                    // Key was gotten iterating over the node, so it must be a non-None value.
-                    self.visit_attr_mut(node.get_attribute_mut(&key).unwrap());
-                    self.previous_field();
+                    self.cursor
+                        .fail("a slice must be the last segment of a delete filter".into());
                 }
             }
-
-            for ident in matching_block_idents {
-                if self.should_remove() {
-                    node.remove_blocks(&ident);
+            Some(Segment::Iterate) => {
+                if self.cursor.is_last() {
+                    let selected: Vec<_> = (0..node.len())
+                        .filter(|index| {
+                            node.get(*index)
+                                .is_some_and(|element| self.cursor.selects(|| expr_result(element)))
+                        })
+                        .collect();
+                    for index in selected.into_iter().rev() {
+                        remove_element(node, index);
+                    }
                 } else {
-                    for block in node.get_blocks_mut(&ident) {
-                        self.next_field();
-                        self.visit_block_mut(block);
-                        self.previous_field();
+                    for element in node.iter_mut() {
+                        if !self.cursor.selects(|| expr_result(element)) {
+                            continue;
+                        }
+                        self.cursor.next_segment();
+                        self.visit_expr_mut(element);
+                        self.cursor.previous_segment();
                     }
                 }
             }
+            // e.g. a field, which an array does not have
+            _ => self.cursor.missing(),
         }
     }
 
     fn visit_object_mut(&mut self, node: &mut hcl_edit::expr::Object) {
-        if let Some(current) = self.current.clone() {
+        if let Some(current) = self.cursor.current() {
             let mut matches = Vec::new();
-            for (key, _) in node.iter() {
-                // some objects are keyed with an Ident
-                if let Some(id) = key.as_ident() {
-                    if id.as_str() == current.name {
-                        matches.push(key.clone());
-                    }
-                }
-                // some objects are keyed with a String Expression
-                if let Some(expr) = key.as_expr() {
-                    if let Some(expr) = expr.as_str() {
-                        if expr == current.name {
-                            matches.push(key.clone());
-                        }
-                    }
+            for (key, value) in node.iter() {
+                if current.matches_object_key(object_key_name(key))
+                    && self.cursor.selects(|| expr_result(value.expr()))
+                {
+                    matches.push(key.clone());
                 }
             }
+            if matches.is_empty() {
+                self.cursor.missing();
+            }
 
             for key in matches {
-                if self.should_remove() {
+                if self.cursor.is_last() {
                     node.remove(&key);
                 } else if let Some(val) = node.get_mut(&key) {
                     // If we haven't reached the end of the query, we need to traverse further into
                     // the AST to determine what needs to be deleted.
-                    self.next_field();
+                    self.cursor.next_segment();
                     self.visit_object_value_mut(val);
-                    self.previous_field();
+                    self.cursor.previous_segment();
                 } else {
                     // Every key in this vec was gotten by iterating over this object, so the value
                     // should exist and this branch should not be reachable.
@@ -136,12 +222,57 @@ impl VisitMut for HclDeleter {
     }
 }
 
-/// given a vector of [`Field`]s, delete the [`Expression`] value that matches that filter
-pub fn delete(fields: Vec<Field>, body: &mut Body) -> Result<(), Box<dyn Error>> {
-    let mut visitor = HclDeleter::new(fields);
-    visitor.visit_body_mut(body);
-    if let Some(err) = visitor.error {
-        return Err(err);
+/// remove an array element, keeping the array's leading whitespace intact when
+/// the first element is removed (e.g. `[1, 2]` becomes `[2]` and not `[ 2]`)
+fn remove_element(array: &mut Array, index: usize) {
+    let removed = array.remove(index);
+    if index == 0 {
+        keep_prefix(&removed, array.get_mut(0));
+    }
+}
+
+/// give the element (or argument) that is now first the leading whitespace of
+/// the `removed` one
+fn keep_prefix(removed: &Expression, first: Option<&mut Expression>) {
+    if let Some(first) = first {
+        first
+            .decor_mut()
+            .set_prefix(removed.decor().prefix().cloned().unwrap_or_default());
+    }
+}
+
+/// given a [`Filter`], delete the [`Expression`] values that match that filter
+///
+/// the filter can combine paths with `|` and `,`, e.g. `.variable | .a, .b`
+/// deletes both `.variable.a` and `.variable.b`
+pub fn delete(filter: &Filter, body: &mut Body) -> Result<(), Box<dyn Error>> {
+    let Some(paths) = filter.paths() else {
+        return Err("delete filters can only combine paths with `|` and `,`".into());
+    };
+    for segments in paths {
+        delete_path(without_optional(segments), body)?;
     }
     Ok(())
 }
+
+fn delete_path(
+    (segments, optional): (Vec<Segment>, Vec<bool>),
+    body: &mut Body,
+) -> Result<(), Box<dyn Error>> {
+    if segments.iter().all(|segment| segment.is_qualifier()) {
+        return Err("cannot delete the whole document (e.g. the identity filter `.`)".into());
+    }
+    if segments
+        .iter()
+        .any(|segment| matches!(segment, Segment::Recursive(_)))
+    {
+        return Err("recursive descent (`..`) is only supported when reading".into());
+    }
+    let mut visitor = HclDeleter {
+        cursor: Cursor::new((segments, optional)),
+    };
+    if visitor.cursor.selects_document(body) {
+        visitor.visit_body_mut(body);
+    }
+    visitor.cursor.finish()
+}
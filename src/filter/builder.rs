@@ -0,0 +1,253 @@
+//! build a [`Filter`] in code instead of parsing a filter expression
+//!
+//! names and labels are always matched exactly (e.g. a `*` in a label is not
+//! a wildcard), so they never need to be quoted or escaped
+
+use hcl::Value;
+
+use super::parser::{Builtin, Comparison, Field, Filter, Kind, Operator, Predicate, Segment};
+
+impl Filter {
+    /// the identity filter `.`, which the other segments are added to
+    ///
+    /// ```
+    /// use hq_rs::{parse_filter, Filter};
+    ///
+    /// let filter = Filter::root()
+    ///     .block("resource", ["aws_s3_bucket", "logs"])
+    ///     .attr("tags")
+    ///     .index(0);
+    /// let parsed = parse_filter(r#".resource{"aws_s3_bucket", "logs"}.tags[0]"#).unwrap();
+    /// assert_eq!(parsed, filter);
+    /// ```
+    pub fn root() -> Self {
+        Filter::Path(Vec::new())
+    }
+
+    /// add a segment to the end of this filter, e.g. `.foo` or `[0]`
+    pub fn segment(self, segment: Segment) -> Self {
+        match self {
+            Filter::Path(mut segments) => {
+                segments.push(segment);
+                Filter::Path(segments)
+            }
+            // a segment after a pipe continues its last path
+            Filter::Pipe(mut filters) => {
+                if let Some(Filter::Path(segments)) = filters.last_mut() {
+                    segments.push(segment);
+                } else {
+                    filters.push(Filter::Path(vec![segment]));
+                }
+                Filter::Pipe(filters)
+            }
+            filter => Filter::Pipe(vec![filter, Filter::Path(vec![segment])]),
+        }
+    }
+
+    /// add a field (with a name that is matched exactly), e.g. `.tags`
+    pub fn attr(self, name: &str) -> Self {
+        self.segment(Field::new(name).into())
+    }
+
+    /// add a field that matches blocks by name and labels, e.g.
+    /// `.resource{"aws_s3_bucket", "logs"}`
+    pub fn block<I>(self, name: &str, labels: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let labels: Vec<_> = labels.into_iter().collect();
+        let labels: Vec<_> = labels.iter().map(AsRef::as_ref).collect();
+        self.segment(Field::labeled(name, &labels).into())
+    }
+
+    /// add any field (e.g. with a pattern or positional labels)
+    pub fn field(self, field: Field) -> Self {
+        self.segment(field.into())
+    }
+
+    /// add an array index, e.g. `[0]` or `[-1]`
+    pub fn index(self, index: isize) -> Self {
+        self.segment(Segment::Index(index))
+    }
+
+    /// add an array slice, e.g. `[1:3]`
+    pub fn slice(self, start: Option<isize>, end: Option<isize>) -> Self {
+        self.segment(Segment::Slice(start, end))
+    }
+
+    /// add `.*`
+    pub fn wildcard(self) -> Self {
+        self.segment(Segment::Wildcard)
+    }
+
+    /// add `[]`
+    pub fn iterate(self) -> Self {
+        self.segment(Segment::Iterate)
+    }
+
+    /// add a recursive descent, e.g. `..tags`
+    pub fn recursive(self, name: &str) -> Self {
+        self.segment(Segment::Recursive(Field::new(name)))
+    }
+
+    /// add a predicate, e.g. `.select(.default)`
+    pub fn select(self, predicate: impl Into<Predicate>) -> Self {
+        self.segment(Segment::Select(predicate.into()))
+    }
+
+    /// keep only the results of the last segment that are of `kind`, e.g.
+    /// `:block`
+    pub fn kind(self, kind: Kind) -> Self {
+        self.segment(Segment::Kind(kind))
+    }
+
+    /// mark the last segment as optional, i.e. `?`
+    pub fn optional(self) -> Self {
+        self.segment(Segment::Optional)
+    }
+
+    /// pass the results of this filter to `filter`, i.e. `|`
+    pub fn pipe(self, filter: impl Into<Filter>) -> Self {
+        match self {
+            Filter::Pipe(mut filters) => {
+                filters.push(filter.into());
+                Filter::Pipe(filters)
+            }
+            this => Filter::Pipe(vec![this, filter.into()]),
+        }
+    }
+
+    /// the results of this filter followed by those of `filter`, i.e. `,`
+    pub fn comma(self, filter: impl Into<Filter>) -> Self {
+        match self {
+            Filter::Comma(mut filters) => {
+                filters.push(filter.into());
+                Filter::Comma(filters)
+            }
+            this => Filter::Comma(vec![this, filter.into()]),
+        }
+    }
+
+    /// the results of `filter` if this filter has none, i.e. `//`
+    pub fn alternative(self, filter: impl Into<Filter>) -> Self {
+        match self {
+            Filter::Alternative(mut filters) => {
+                filters.push(filter.into());
+                Filter::Alternative(filters)
+            }
+            this => Filter::Alternative(vec![this, filter.into()]),
+        }
+    }
+
+    /// compare the results of this filter with those of `filter`, e.g. `==`
+    pub fn compare(self, comparison: Comparison, filter: impl Into<Filter>) -> Self {
+        Filter::Compare(Box::new(self), comparison, Box::new(filter.into()))
+    }
+
+    /// combine the results of this filter with those of `filter`, e.g. `+`
+    pub fn arithmetic(self, operator: Operator, filter: impl Into<Filter>) -> Self {
+        Filter::Arithmetic(Box::new(self), operator, Box::new(filter.into()))
+    }
+
+    /// whether the results of this filter and of `filter` are true, i.e. `and`
+    pub fn and(self, filter: impl Into<Filter>) -> Self {
+        match self {
+            Filter::And(mut filters) => {
+                filters.push(filter.into());
+                Filter::And(filters)
+            }
+            this => Filter::And(vec![this, filter.into()]),
+        }
+    }
+
+    /// whether the results of this filter or of `filter` are true, i.e. `or`
+    pub fn or(self, filter: impl Into<Filter>) -> Self {
+        match self {
+            Filter::Or(mut filters) => {
+                filters.push(filter.into());
+                Filter::Or(filters)
+            }
+            this => Filter::Or(vec![this, filter.into()]),
+        }
+    }
+
+    /// a literal value, e.g. `"default"`
+    pub fn literal(value: impl Into<Value>) -> Self {
+        Filter::Literal(value.into())
+    }
+}
+
+impl From<Builtin> for Filter {
+    fn from(builtin: Builtin) -> Self {
+        Filter::Builtin(builtin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_filter;
+
+    #[test]
+    fn built_path() {
+        let filter = Filter::root()
+            .attr("ingress")
+            .iterate()
+            .attr("from_port")
+            .optional()
+            .slice(Some(1), None)
+            .wildcard();
+        let parsed = parse_filter(".ingress[].from_port?[1:].*").expect("parse error");
+        assert_eq!(parsed, filter);
+    }
+
+    #[test]
+    fn built_operators() {
+        let filter = Filter::root()
+            .attr("variable")
+            .pipe(
+                Filter::root()
+                    .attr("default")
+                    .alternative(Filter::literal("none")),
+            )
+            .comma(Filter::from(Builtin::Labels))
+            .pipe(Builtin::Length)
+            .attr("x");
+        let parsed = parse_filter(r#"(.variable | .default // "none"), labels | length | .x"#)
+            .expect("parse error");
+        assert_eq!(parsed, filter);
+    }
+
+    #[test]
+    fn built_expression() {
+        let filter = Filter::root()
+            .attr("resource")
+            .wildcard()
+            .select(
+                Filter::root()
+                    .attr("count")
+                    .compare(Comparison::Gt, Filter::literal(1))
+                    .and(Filter::root().attr("enabled"))
+                    .and(Builtin::Not),
+            )
+            .pipe(
+                Filter::root()
+                    .attr("min_size")
+                    .arithmetic(Operator::Add, Filter::root().attr("max_size"))
+                    .or(Filter::literal(false)),
+            );
+        let parsed = parse_filter(
+            ".resource.*.select(.count > 1 and .enabled and not) | .min_size + .max_size or false",
+        )
+        .expect("parse error");
+        assert_eq!(parsed, filter);
+    }
+
+    #[test]
+    fn built_names_are_exact() {
+        let filter = Filter::root().block("module", ["prod-*", "say \"hi\""]);
+        let parsed = parse_filter(r#".module{"prod-\*", "say \"hi\""}"#).expect("parse error");
+        assert_eq!(parsed, filter);
+    }
+}
@@ -0,0 +1,520 @@
+//! format a parsed [`Filter`] as a canonical filter string, which
+//! [`parse_filter`][super::parser::parse_filter] parses back into the same
+//! [`Filter`]
+
+use std::fmt::{self, Display, Formatter, Write};
+
+use hcl::Value;
+use hcl_edit::Ident;
+
+use super::{
+    parser::{
+        Builtin, Comparison, Field, Filter, Kind, Label, Operator, Predicate, Segment, TemplatePart,
+    },
+    pattern::Pattern,
+};
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Path(segments) if segments.is_empty() => f.write_char('.'),
+            Filter::Path(segments) => segments.iter().try_for_each(|segment| segment.fmt(f)),
+            Filter::Pipe(filters) => fmt_operands(f, self, filters, " | "),
+            Filter::Comma(filters) => fmt_operands(f, self, filters, ", "),
+            Filter::Alternative(filters) => fmt_operands(f, self, filters, " // "),
+            Filter::Builtin(builtin) => builtin.fmt(f),
+            Filter::Literal(value) => fmt_value(f, value),
+            Filter::Array(filter) => match &**filter {
+                // `["a"]` and `[0]` would be a name and an index
+                Filter::Literal(value) if is_ambiguous_element(value) => write!(f, "[({filter})]"),
+                filter => write!(f, "[{filter}]"),
+            },
+            Filter::Object(entries) => {
+                f.write_char('{')?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    fmt_key(f, key)?;
+                    // `,` separates the keys, so a value that has a `,` (or a
+                    // `|`) is grouped
+                    if matches!(value, Filter::Pipe(_) | Filter::Comma(_)) {
+                        write!(f, "({value})")?;
+                    } else {
+                        write!(f, "{value}")?;
+                    }
+                }
+                f.write_char('}')
+            }
+            Filter::Template(parts) => {
+                f.write_char('"')?;
+                for part in parts {
+                    match part {
+                        TemplatePart::Text(text) => {
+                            text.chars().try_for_each(|c| fmt_char(f, c))?
+                        }
+                        TemplatePart::Interpolation(filter) => write!(f, "\\({filter})")?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Filter::Compare(left, comparison, right) => {
+                // comparisons cannot be chained, so both sides of one that
+                // is an operand are grouped
+                fmt_operand(f, self, left, false)?;
+                write!(f, " {comparison} ")?;
+                fmt_operand(f, self, right, false)
+            }
+            Filter::Arithmetic(left, operator, right) => {
+                // `a - (b - c)` is not `a - b - c`
+                fmt_operand(f, self, left, true)?;
+                write!(f, " {operator} ")?;
+                fmt_operand(f, self, right, false)
+            }
+            Filter::And(filters) => fmt_operands(f, self, filters, " and "),
+            Filter::Or(filters) => fmt_operands(f, self, filters, " or "),
+        }
+    }
+}
+
+impl Filter {
+    /// how tightly an operator binds its operands (`|` least of all), which
+    /// decides whether an operand needs parentheses
+    fn precedence(&self) -> u8 {
+        match self {
+            Filter::Pipe(_) => 0,
+            Filter::Comma(_) => 1,
+            Filter::Alternative(_) => 2,
+            Filter::Or(_) => 3,
+            Filter::And(_) => 4,
+            Filter::Compare(..) => 5,
+            Filter::Arithmetic(_, Operator::Add | Operator::Subtract, _) => 6,
+            Filter::Arithmetic(..) => 7,
+            Filter::Path(_)
+            | Filter::Builtin(_)
+            | Filter::Literal(_)
+            | Filter::Template(_)
+            | Filter::Array(_)
+            | Filter::Object(_) => 8,
+        }
+    }
+}
+
+fn fmt_operands(
+    f: &mut Formatter<'_>,
+    operator: &Filter,
+    operands: &[Filter],
+    separator: &str,
+) -> fmt::Result {
+    for (index, operand) in operands.iter().enumerate() {
+        if index > 0 {
+            f.write_str(separator)?;
+        }
+        fmt_operand(f, operator, operand, false)?;
+    }
+    Ok(())
+}
+
+/// an operand that is itself an operator of the same (or lower) precedence
+/// has to be grouped to parse back into the same filter, except for the left
+/// operand of an operator that is applied from left to right
+fn fmt_operand(
+    f: &mut Formatter<'_>,
+    operator: &Filter,
+    operand: &Filter,
+    left_to_right: bool,
+) -> fmt::Result {
+    let grouped = if left_to_right {
+        operand.precedence() < operator.precedence()
+    } else {
+        operand.precedence() <= operator.precedence()
+    };
+    if grouped {
+        write!(f, "({operand})")
+    } else {
+        write!(f, "{operand}")
+    }
+}
+
+impl Display for Segment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Segment::Field(field) => field.fmt(f),
+            Segment::Index(index) => write!(f, "[{index}]"),
+            Segment::Slice(start, end) => {
+                f.write_char('[')?;
+                if let Some(start) = start {
+                    write!(f, "{start}")?;
+                }
+                f.write_char(':')?;
+                if let Some(end) = end {
+                    write!(f, "{end}")?;
+                }
+                f.write_char(']')
+            }
+            Segment::Wildcard => f.write_str(".*"),
+            Segment::Iterate => f.write_str("[]"),
+            Segment::Recursive(field) => write!(f, ".{field}"),
+            Segment::Select(predicate) => write!(f, ".select({predicate})"),
+            Segment::Kind(kind) => write!(f, ":{kind}"),
+            Segment::Optional => f.write_char('?'),
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.pattern {
+            Some(pattern) if pattern.is_regex => write!(f, ".{pattern}")?,
+            // a glob that only has identifier characters and `*` does not
+            // need quotes
+            Some(pattern) if !pattern.case_insensitive && is_glob_name(&pattern.source) => {
+                write!(f, ".{}", pattern.source)?
+            }
+            Some(pattern) => write!(f, ".[{pattern}]")?,
+            None if Ident::try_new(&self.name).is_ok() => write!(f, ".{}", self.name)?,
+            None => {
+                f.write_str(".[")?;
+                fmt_quoted(f, &self.name, true)?;
+                f.write_char(']')?;
+            }
+        }
+
+        if !self.labels.is_empty() || self.label_prefix {
+            f.write_char('{')?;
+            for (index, label) in self.labels.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                label.fmt(f)?;
+            }
+            if self.label_prefix {
+                if !self.labels.is_empty() {
+                    f.write_str(", ")?;
+                }
+                f.write_str("...")?;
+            }
+            f.write_char('}')?;
+        }
+        Ok(())
+    }
+}
+
+/// whether `glob` can be written as an unquoted name, e.g. `aws_*`
+fn is_glob_name(glob: &str) -> bool {
+    Ident::try_new(glob.replace('*', "_")).is_ok() && !glob.starts_with('*')
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Label::Exact(label) => fmt_quoted(f, label, true),
+            Label::Any => f.write_char('*'),
+            Label::Pattern(pattern) => pattern.fmt(f),
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_regex {
+            // only quotes need to be escaped in a regex
+            write!(f, "~\"{}\"", self.source.replace('"', "\\\""))?;
+        } else {
+            // the escapes of a glob are also escapes in a quoted label
+            f.write_char('"')?;
+            let mut chars = self.source.chars();
+            while let Some(c) = chars.next() {
+                if c == '\\' {
+                    f.write_char(c)?;
+                    if let Some(escaped) = chars.next() {
+                        f.write_char(escaped)?;
+                    }
+                } else {
+                    fmt_char(f, c)?;
+                }
+            }
+            f.write_char('"')?;
+        }
+        if self.case_insensitive {
+            f.write_char('i')?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Predicate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.filter.fmt(f)
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+            Comparison::Contains => "contains",
+        })
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_char(match self {
+            Operator::Add => '+',
+            Operator::Subtract => '-',
+            Operator::Multiply => '*',
+            Operator::Divide => '/',
+            Operator::Remainder => '%',
+        })
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Attr => "attr",
+            Kind::Block => "block",
+            Kind::Object => "object",
+            Kind::List => "list",
+        })
+    }
+}
+
+impl Display for Builtin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Builtin::Keys => f.write_str("keys"),
+            Builtin::Labels => f.write_str("labels"),
+            Builtin::Ident => f.write_str("ident"),
+            Builtin::Length => f.write_str("length"),
+            Builtin::Type => f.write_str("type"),
+            Builtin::Has(key) => {
+                f.write_str("has(")?;
+                fmt_value(f, key)?;
+                f.write_char(')')
+            }
+            Builtin::ToEntries => f.write_str("to_entries"),
+            Builtin::Not => f.write_str("not"),
+            Builtin::Parent => f.write_str("parent"),
+            Builtin::Ancestors => f.write_str("ancestors"),
+            Builtin::Sort => f.write_str("sort"),
+            Builtin::SortBy(filter) => write!(f, "sort_by({filter})"),
+            Builtin::GroupBy(filter) => write!(f, "group_by({filter})"),
+            Builtin::Unique => f.write_str("unique"),
+            Builtin::Min => f.write_str("min"),
+            Builtin::Max => f.write_str("max"),
+            Builtin::Map(filter) => write!(f, "map({filter})"),
+            Builtin::Add => f.write_str("add"),
+            Builtin::Split(separator) => fmt_call(f, "split", separator),
+            Builtin::Join(separator) => fmt_call(f, "join", separator),
+            Builtin::Test(pattern) => write!(f, "test({pattern})"),
+            Builtin::Capture(pattern) => write!(f, "capture({pattern})"),
+            Builtin::StartsWith(prefix) => fmt_call(f, "startswith", prefix),
+            Builtin::EndsWith(suffix) => fmt_call(f, "endswith", suffix),
+            Builtin::LtrimStr(prefix) => fmt_call(f, "ltrimstr", prefix),
+            Builtin::AsciiDowncase => f.write_str("ascii_downcase"),
+        }
+    }
+}
+
+/// a literal value (e.g. from `--arg-hcl`), in the syntax of a filter
+fn fmt_value(f: &mut Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::String(string) => fmt_quoted(f, string, false),
+        Value::Array(array) if matches!(&array[..], [element] if is_ambiguous_element(element)) => {
+            f.write_str("[(")?;
+            fmt_value(f, &array[0])?;
+            f.write_str(")]")
+        }
+        Value::Array(array) => {
+            f.write_char('[')?;
+            for (index, element) in array.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                fmt_value(f, element)?;
+            }
+            f.write_char(']')
+        }
+        Value::Object(object) => {
+            f.write_char('{')?;
+            for (index, (key, value)) in object.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                fmt_key(f, key)?;
+                fmt_value(f, value)?;
+            }
+            f.write_char('}')
+        }
+        // null, booleans and numbers are the same as in HCL
+        value => write!(f, "{value}"),
+    }
+}
+
+/// whether a list with only `value` would be read as a quoted name or an index
+/// (e.g. `["a"]` or `[0]`), unless `value` is in parentheses
+fn is_ambiguous_element(value: &Value) -> bool {
+    matches!(value, Value::String(_) | Value::Number(_))
+}
+
+/// the key of an object, which is only quoted when it is not an identifier
+fn fmt_key(f: &mut Formatter<'_>, key: &str) -> fmt::Result {
+    if Ident::try_new(key).is_ok() {
+        f.write_str(key)?;
+    } else {
+        fmt_quoted(f, key, false)?;
+    }
+    f.write_str(": ")
+}
+
+/// `text` between double quotes, with the escapes that parse back into it
+///
+/// a quoted name or label is a glob, so its wildcards are escaped too
+fn fmt_quoted(f: &mut Formatter<'_>, text: &str, escape_wildcards: bool) -> fmt::Result {
+    f.write_char('"')?;
+    for c in text.chars() {
+        if escape_wildcards && matches!(c, '*' | '?') {
+            f.write_char('\\')?;
+        }
+        fmt_char(f, c)?;
+    }
+    f.write_char('"')
+}
+
+/// a function with a string argument, e.g. `split("/")`
+fn fmt_call(f: &mut Formatter<'_>, name: &str, argument: &str) -> fmt::Result {
+    write!(f, "{name}(")?;
+    fmt_quoted(f, argument, false)?;
+    f.write_char(')')
+}
+
+fn fmt_char(f: &mut Formatter<'_>, c: char) -> fmt::Result {
+    match c {
+        '"' => f.write_str("\\\""),
+        '\\' => f.write_str("\\\\"),
+        '\n' => f.write_str("\\n"),
+        '\r' => f.write_str("\\r"),
+        '\t' => f.write_str("\\t"),
+        c if c.is_control() => write!(f, "\\u{:04x}", c as u32),
+        c => f.write_char(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_filter, parse_filter_with_variables, Variables};
+
+    /// `input` is already canonical, so it is formatted exactly as it was
+    /// parsed
+    fn assert_canonical(input: &str) {
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(input, filter.to_string());
+    }
+
+    /// the canonical form of `input` parses back into the same filter
+    fn assert_round_trip(input: &str) {
+        let filter = parse_filter(input).expect("parse error");
+        let formatted = filter.to_string();
+        let reparsed = parse_filter(&formatted).expect("reparse error");
+        assert_eq!(filter, reparsed, "{input} was formatted as {formatted}");
+    }
+
+    #[test]
+    fn canonical_variables() {
+        // values of `--arg-hcl` are formatted as the literals they are equal to
+        let object = hcl::Map::from_iter([
+            (String::from("a"), Value::from(1)),
+            (String::from("b c"), Value::from(vec!["d"])),
+        ]);
+        let variables = Variables::from([
+            (String::from("list"), Value::from(vec![1, 2])),
+            (String::from("object"), Value::Object(object)),
+        ]);
+        let filter = parse_filter_with_variables(".a == $list, .b + $object", &variables)
+            .expect("parse error");
+        let formatted = filter.to_string();
+        assert_eq!(r#".a == [1, 2], .b + {a: 1, "b c": [("d")]}"#, formatted);
+        assert_canonical(&formatted);
+    }
+
+    #[test]
+    fn canonical_filters() {
+        assert_canonical(".");
+        assert_canonical(".a.b[0][-1][1:][:2][]");
+        assert_canonical(r#".resource{"aws_instance", *, ...}.tags"#);
+        assert_canonical("..tags.*");
+        assert_canonical(r#".variable.select(.default == "x").select(. >= 1)"#);
+        assert_canonical(".tags?.Name[0]?");
+        assert_canonical(r#".a | .b, .c // "d" // null"#);
+        assert_canonical("(.a | .b), .c");
+        assert_canonical("(.a, .b) // .c");
+        assert_canonical(r#".variable | labels, has("default"), has(0), keys"#);
+        assert_canonical(r#".aws_*{"prod-*", ~"^app-[0-9]+$"i}"#);
+        assert_canonical(r#".~"^aws_"i"#);
+        assert_canonical(".cool-module.名前");
+        assert_canonical(".min_size + .max_size * 2 - 1");
+        assert_canonical("(.a + .b) * (.c - .d) % 3 / 2");
+        assert_canonical(".a - (.b - .c)");
+        assert_canonical(r#".count > 1 and .x != null or (.a | not)"#);
+        assert_canonical("(.a or .b) and .c");
+        assert_canonical(".resource.*.select(.count >= 2 and .enabled)");
+        assert_canonical("(.a == .b) == true");
+        assert_canonical(".module | sort_by(.source), group_by(labels | [0]), map(.a, .b)");
+        assert_canonical(".ports | sort, unique, min, max, add");
+        assert_canonical(
+            r#"[.module.source, 1] | {name: (labels | [0]), "the ref": .a // .b, c: {}}"#,
+        );
+        assert_canonical(r#"[("a")], [(0)], [0]"#);
+        assert_canonical(
+            r#".source | split("/"), join(", "), startswith("git::"), endswith(".git")"#,
+        );
+        assert_canonical(
+            r#".source | test(~"ref=v1"i), capture(~"(?<ref>[^=]+)$"), ltrimstr("x")"#,
+        );
+        assert_canonical(r#".module | "\(labels | [0]): \"\(.source | ascii_downcase)\"\n""#);
+    }
+
+    #[test]
+    fn quoted_filters() {
+        assert_canonical(r#".["map@key1"]"#);
+        assert_canonical(r#".module{"with space", "say \"hi\"\n", "back\\slash"}"#);
+        assert_canonical(r#".["a\*b"]{"star\*", "\?"}"#);
+        assert_canonical(r#".["Name"i]"#);
+    }
+
+    #[test]
+    fn round_trip_filters() {
+        assert_round_trip(r#".module{'it\'s', "é\t"}"#);
+        assert_round_trip(".['a*b']");
+        assert_round_trip(".['*_id']");
+        assert_round_trip(r#".a{~"say \"hi\""}"#);
+        assert_round_trip(r#"select(.a contains "x*") | .b"#);
+        assert_round_trip("((.a | .b) | .c)");
+        assert_round_trip(".a[1:]|.b ,.c");
+        assert_round_trip("((.a * 2) + (1 - .b))");
+        assert_round_trip(".a//.b or.c");
+        assert_round_trip(".a:attr?[]:object");
+    }
+
+    #[test]
+    fn built_filter() {
+        let filter = Filter::Path(vec![
+            Field::labeled("module", &["with space", "quote\"", "star*"]).into(),
+            Field::new("not an identifier").into(),
+        ]);
+        let formatted = filter.to_string();
+        assert_eq!(
+            r#".module{"with space", "quote\"", "star\*"}.["not an identifier"]"#,
+            formatted
+        );
+        assert_eq!(filter, parse_filter(&formatted).expect("parse error"));
+    }
+}
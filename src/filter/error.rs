@@ -1,31 +1,158 @@
 use core::fmt;
+use std::ops::Range;
 
 use annotate_snippets::{Level, Renderer, Snippet};
-use pest::{error::InputLocation, RuleType};
+use pest::{
+    error::{ErrorVariant, InputLocation, LineColLocation},
+    RuleType,
+};
 
+/// a filter that could not be parsed, which is rendered as a diagnostic that
+/// points at the problem in the filter
+///
+/// `{:?}` renders it with colors (ANSI escape codes) and `{}` without them
 pub struct FilterError<R> {
     parsing_error: pest::error::Error<R>,
+    label: String,
+    help: Vec<String>,
 }
 
-impl<R> fmt::Debug for FilterError<R>
+impl<R> FilterError<R>
 where
     R: RuleType,
 {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let input = self.parsing_error.line();
-        let pos = match self.parsing_error.location {
+    /// describe what the parser expected (or did not expect) at the location
+    /// of `parsing_error`, e.g. "expected `.`, `[` or `{`"
+    ///
+    /// `describe` names what a rule matches (e.g. "`[`"), and the rules it
+    /// does not name are left out
+    pub fn new<F>(parsing_error: pest::error::Error<R>, describe: F) -> Self
+    where
+        F: Fn(&R) -> Option<String>,
+    {
+        let label = match &parsing_error.variant {
+            ErrorVariant::CustomError { message } => message.clone(),
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => {
+                let positives = describe_all(positives, &describe);
+                let negatives = describe_all(negatives, &describe);
+                match (negatives.is_empty(), positives.is_empty()) {
+                    (true, true) => String::from("unexpected token"),
+                    (true, false) => format!("expected {}", list(&positives)),
+                    (false, true) => format!("unexpected {}", list(&negatives)),
+                    (false, false) => format!(
+                        "unexpected {}; expected {}",
+                        list(&negatives),
+                        list(&positives)
+                    ),
+                }
+            }
+        };
+        FilterError {
+            parsing_error,
+            label,
+            help: Vec::new(),
+        }
+    }
+
+    /// add a note on how to fix the filter
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// what went wrong, e.g. "expected `.`, `[` or `{`"
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// the notes on how to fix the filter
+    pub fn help(&self) -> &[String] {
+        &self.help
+    }
+
+    /// the (byte) range of the filter that the error points at
+    pub fn span(&self) -> Range<usize> {
+        match self.parsing_error.location {
             InputLocation::Pos(pos) => pos..pos,
             InputLocation::Span((start, end)) => start..end,
+        }
+    }
+
+    /// render the diagnostic, with colors if `styled`
+    pub fn render(&self, styled: bool) -> String {
+        let input = self.parsing_error.line();
+        // the location is within the whole filter, but only its line is shown
+        let (line, pos) = match self.parsing_error.line_col {
+            LineColLocation::Pos((line, col)) => (line, byte_range(input, col, col)),
+            LineColLocation::Span((line, start), (end_line, end)) if line == end_line => {
+                (line, byte_range(input, start, end))
+            }
+            LineColLocation::Span((line, start), _) => {
+                (line, byte_range(input, start, input.chars().count() + 1))
+            }
         };
 
-        let message = Level::Error.title("failed to parse filter").snippet(
-            Snippet::source(input).annotation(Level::Error.span(pos).label("unexpected token")),
-        );
+        let message = Level::Error
+            .title("failed to parse filter")
+            .snippet(
+                Snippet::source(input)
+                    .line_start(line)
+                    .annotation(Level::Error.span(pos).label(&self.label)),
+            )
+            .footers(self.help.iter().map(|help| Level::Help.title(help)));
 
-        let renderer = Renderer::styled();
-        let rendered = renderer.render(message);
+        let renderer = if styled {
+            Renderer::styled()
+        } else {
+            Renderer::plain()
+        };
+        // `format!` ends the borrow of `renderer` before it is dropped
+        format!("{}", renderer.render(message))
+    }
+}
+
+/// the descriptions of `rules`, without duplicates
+fn describe_all<R, F>(rules: &[R], describe: F) -> Vec<String>
+where
+    F: Fn(&R) -> Option<String>,
+{
+    let mut descriptions: Vec<String> = Vec::new();
+    for description in rules.iter().filter_map(describe) {
+        if !descriptions.contains(&description) {
+            descriptions.push(description);
+        }
+    }
+    descriptions
+}
+
+/// e.g. "`.`, `[` or `{`"
+fn list(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [items @ .., last] => format!("{} or {last}", items.join(", ")),
+    }
+}
+
+/// the byte range of the (1-based) columns `start..end` of `line`
+fn byte_range(line: &str, start: usize, end: usize) -> Range<usize> {
+    let byte = |col: usize| {
+        line.char_indices()
+            .nth(col.saturating_sub(1))
+            .map_or(line.len(), |(byte, _)| byte)
+    };
+    byte(start)..byte(end)
+}
 
-        write!(f, "{}", rendered)
+impl<R> fmt::Debug for FilterError<R>
+where
+    R: RuleType,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(true))
     }
 }
 
@@ -34,7 +161,7 @@ where
     R: RuleType,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        f.write_str(&self.render(false))
     }
 }
 
@@ -44,17 +171,9 @@ impl<R> From<pest::error::Error<R>> for Box<FilterError<R>>
 where
     R: RuleType,
 {
+    /// an error that names the rules it expected as they are called in the
+    /// grammar
     fn from(value: pest::error::Error<R>) -> Self {
-        match value.variant {
-            pest::error::ErrorVariant::ParsingError {
-                positives: _,
-                negatives: _,
-            } => Box::new(FilterError {
-                parsing_error: value.clone(),
-            }),
-            _ => {
-                unimplemented!()
-            }
-        }
+        Box::new(FilterError::new(value, |rule| Some(format!("`{rule:?}`"))))
     }
 }
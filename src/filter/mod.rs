@@ -1,6 +1,14 @@
 //! a parser for hq filter expressions
 //!
 //! see [`parse_filter`][parser::parse_filter]
+//!
+//! a [`Filter`][parser::Filter] formats (e.g. with `to_string()`) as a
+//! canonical filter expression that parses back into the same filter, and
+//! [`Filter::tree`][parser::Filter::tree] shows how it was parsed
 
+mod builder;
+mod display;
 pub mod error;
 pub mod parser;
+pub mod pattern;
+mod tree;
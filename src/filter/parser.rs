@@ -1,74 +1,1279 @@
-use pest::Parser;
+use std::{collections::HashMap, ops::Range};
+
+use hcl::{Number, Value};
+use pest::{
+    error::{Error, ErrorVariant, InputLocation},
+    iterators::{Pair, Pairs},
+    Parser,
+};
 use pest_derive::Parser;
 
-use super::error::FilterError;
+use super::{error::FilterError, pattern::Pattern};
 
 #[derive(Parser)]
 #[grammar = "filter/grammar.pest"]
-pub struct Filter {}
+pub struct FilterParser {}
+
+/// a parsed filter (see [`parse_filter`])
+///
+/// e.g. the filter `'.variable | .default, .type'` is a pipe of two filters:
+///
+/// * the path `.variable`
+/// * the comma of the paths `.default` and `.type`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// a chain of segments that is applied to each input, e.g. `.foo{"bar"}.baz`
+    ///
+    /// the identity filter `.` is a path without any segments
+    Path(Vec<Segment>),
+    /// each filter is applied to the results of the previous one, e.g.
+    /// `.variable | .default`
+    Pipe(Vec<Filter>),
+    /// the results of each filter (applied to the same input) one after
+    /// another, e.g. `.version, .module.version`
+    Comma(Vec<Filter>),
+    /// a built-in function that is applied to each input, e.g. `labels`
+    Builtin(Builtin),
+    /// the results of the first filter that has any results other than
+    /// `false` or `null` (or else the results of the last filter), e.g.
+    /// `.variable.default // "none"`
+    Alternative(Vec<Filter>),
+    /// a literal value, e.g. `"none"`, `42`, `true` or `null`
+    Literal(Value),
+    /// a list of all the results of a filter, e.g. `[.module.source]`
+    Array(Box<Filter>),
+    /// an object with the results of a filter for each key, e.g.
+    /// `{name: (.variable | labels), default: .variable.default}` (one object
+    /// for each combination of results)
+    Object(Vec<(String, Filter)>),
+    /// a string with the results of filters interpolated into it, e.g.
+    /// `"\(.name)-logs"` (one string for each combination of results)
+    Template(Vec<TemplatePart>),
+    /// whether each result of the left filter compares true with each result
+    /// of the right filter, e.g. `.count > 1`
+    Compare(Box<Filter>, Comparison, Box<Filter>),
+    /// the results of both filters combined by an arithmetic operator, e.g.
+    /// `.min_size + .max_size`
+    Arithmetic(Box<Filter>, Operator, Box<Filter>),
+    /// whether the results of every filter are something other than `false`
+    /// or `null`, e.g. `.enabled and .count > 0`
+    And(Vec<Filter>),
+    /// whether the results of any filter are something other than `false`
+    /// or `null`, e.g. `.public or .shared`
+    Or(Vec<Filter>),
+}
+
+/// a built-in function of a [`Filter`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Builtin {
+    /// the names of a block's attributes and blocks, of an object's keys or
+    /// of an array's indices (in document order)
+    Keys,
+    /// the labels of a block
+    Labels,
+    /// the identifier of a block
+    Ident,
+    /// the number of elements of an array, keys of an object, characters of a
+    /// string or attributes and blocks of a block
+    Length,
+    /// the type of the input (e.g. `"block"`, `"string"` or `"array"`)
+    Type,
+    /// whether a block has an attribute or block with the given name, an
+    /// object has the given key or an array has the given index
+    Has(Value),
+    /// a block's attributes or an object's entries as an array of
+    /// `{ key = ..., value = ... }` objects
+    ToEntries,
+    /// whether the input is `false` or `null`
+    Not,
+    /// the block, object, array or expression that the input is part of
+    /// (the whole document for a top-level attribute or block)
+    Parent,
+    /// everything that the input is part of, nearest first and ending with
+    /// the whole document
+    Ancestors,
+    /// the elements in order (values first, e.g. `null`, then booleans,
+    /// numbers, strings, arrays and objects, and then blocks)
+    Sort,
+    /// the elements in the order of the results of the filter for each of
+    /// them
+    SortBy(Box<Filter>),
+    /// the elements as groups that have the same results of the filter, in
+    /// the order of those results
+    GroupBy(Box<Filter>),
+    /// the elements in order (see [`Builtin::Sort`]) without duplicates
+    Unique,
+    /// the smallest element (see [`Builtin::Sort`])
+    Min,
+    /// the largest element (see [`Builtin::Sort`])
+    Max,
+    /// the results of the filter for each element
+    Map(Box<Filter>),
+    /// the sum of the elements (see [`Operator::Add`])
+    Add,
+    /// a string split at each occurrence of the separator
+    Split(String),
+    /// the elements of an array joined by the separator (strings, numbers,
+    /// booleans and `null`, which is an empty string)
+    Join(String),
+    /// whether a string matches the regex
+    Test(Pattern),
+    /// the named groups of the first match of the regex in a string, as an
+    /// object
+    Capture(Pattern),
+    /// whether a string starts with the prefix
+    StartsWith(String),
+    /// whether a string ends with the suffix
+    EndsWith(String),
+    /// a string without the prefix (if it starts with it)
+    LtrimStr(String),
+    /// a string with its ASCII letters in lowercase
+    AsciiDowncase,
+}
+
+/// a part of a [`Filter::Template`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplatePart {
+    /// text as it is
+    Text(String),
+    /// the results of a filter, e.g. `\(.name)`
+    Interpolation(Filter),
+}
+
+impl Filter {
+    /// the paths this filter consists of, if it only combines paths with `|`
+    /// and `,`
+    ///
+    /// e.g. `.a | .b, .c` consists of the paths `.a.b` and `.a.c`
+    pub(crate) fn paths(&self) -> Option<Vec<Vec<Segment>>> {
+        match self {
+            Filter::Path(segments) => Some(vec![segments.clone()]),
+            Filter::Pipe(filters) => {
+                let mut paths = vec![Vec::new()];
+                for filter in filters {
+                    let mut next_paths = Vec::new();
+                    for path in &paths {
+                        for suffix in filter.paths()? {
+                            next_paths.push([path.clone(), suffix].concat());
+                        }
+                    }
+                    paths = next_paths;
+                }
+                Some(paths)
+            }
+            Filter::Comma(filters) => {
+                let mut paths = Vec::new();
+                for filter in filters {
+                    paths.append(&mut filter.paths()?);
+                }
+                Some(paths)
+            }
+            // a function's result is not a location in the document
+            Filter::Builtin(_)
+            | Filter::Alternative(_)
+            | Filter::Literal(_)
+            | Filter::Template(_)
+            | Filter::Array(_)
+            | Filter::Object(_)
+            | Filter::Compare(..)
+            | Filter::Arithmetic(..)
+            | Filter::And(_)
+            | Filter::Or(_) => None,
+        }
+    }
+}
+
+impl From<Vec<Segment>> for Filter {
+    fn from(segments: Vec<Segment>) -> Self {
+        Filter::Path(segments)
+    }
+}
+
+/// one segment of a [`Filter::Path`]
+///
+/// e.g. for the filter `'.foo{"bar"}.baz[0]'` there are three segments:
+///
+/// * the field with name "foo" and label "bar"
+/// * the field with name "baz"
+/// * the index 0
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    /// an attribute, block or object key, e.g. `.foo{"bar"}`
+    Field(Field),
+    /// an array index, e.g. `[0]` or `[-1]` (counting from the end)
+    Index(isize),
+    /// an array slice, e.g. `[1:3]`, `[:2]` or `[-2:]`
+    Slice(Option<isize>, Option<isize>),
+    /// every attribute and block of a body or every value of an object, i.e. `.*`
+    Wildcard,
+    /// every element of an array or every value of an object, i.e. `[]`
+    Iterate,
+    /// every attribute, block or object key with a matching name at any
+    /// depth, e.g. `..tags` (only supported when reading)
+    Recursive(Field),
+    /// only the results that match a predicate, e.g.
+    /// `.select(.instance_type == "t2.micro")`
+    Select(Predicate),
+    /// only the results of the previous segment that are of a kind, e.g.
+    /// `.lifecycle:block` (which is missing when there is only an attribute
+    /// `lifecycle`)
+    Kind(Kind),
+    /// marks the previous segment as optional, i.e. `?`, so that it does
+    /// not fail when it matches nothing, e.g. `.tags?.Name`
+    Optional,
+}
+
+impl Segment {
+    /// whether this segment selects the attribute `key` of a body
+    pub(crate) fn matches_attr(&self, key: &str) -> bool {
+        match self {
+            Segment::Field(field) => field.matches_attr(key),
+            Segment::Wildcard => true,
+            _ => false,
+        }
+    }
+
+    /// whether this segment selects the block `ident` (with `labels`) of a body
+    pub(crate) fn matches_block<'a>(
+        &self,
+        ident: &str,
+        labels: impl IntoIterator<Item = &'a str>,
+    ) -> bool {
+        match self {
+            Segment::Field(field) => field.matches_block(ident, labels),
+            Segment::Wildcard => true,
+            _ => false,
+        }
+    }
+
+    /// whether this segment selects the value at `key` of an object
+    ///
+    /// `key` is `None` for object keys that are neither identifiers nor
+    /// strings (e.g. `(var.key)`), which only a wildcard can select
+    pub(crate) fn matches_object_key(&self, key: Option<&str>) -> bool {
+        match self {
+            Segment::Field(field) => field.matches_object_key(key),
+            Segment::Wildcard | Segment::Iterate => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<Field> for Segment {
+    fn from(field: Field) -> Self {
+        Segment::Field(field)
+    }
+}
 
-/// one segment of a filter ([`parse_filter`] returns `Vec<Field>`)
+/// the name and labels of a [`Segment::Field`]
 ///
-/// e.g. for the filter `'.foo{"bar"}.baz'` there are two segments:
+/// e.g. for the filter `'.foo{"bar"}.baz'` there are two fields:
 ///
 /// * the name "foo" and the label "bar"
 /// * the name "baz"
+///
+/// labels are matched positionally against a block's labels, so
+/// `.resource{"aws_instance", "web"}` only matches blocks whose first label is
+/// "aws_instance" and whose second (and last) label is "web"
+///
+/// names and labels can also be patterns, e.g. `.aws_*{"prod-*"}` or
+/// `.~"^aws_"{~"^app-[0-9]+$"i}`
 #[derive(Clone, Debug, PartialEq)]
 pub struct Field {
     /// an attribute or block name
     pub name: String,
+    /// the pattern that names are matched against instead of `name` (which
+    /// is the pattern's source)
+    pub pattern: Option<Pattern>,
     /// block labels
-    pub labels: Vec<String>,
+    pub labels: Vec<Label>,
+    /// whether `labels` only have to match the first labels of a block
+    /// (e.g. `{"aws_instance", ...}`) rather than all of them
+    pub label_prefix: bool,
+}
+
+/// one of the labels of a [`Field`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Label {
+    /// a label with this exact value, e.g. `"foo"`
+    Exact(String),
+    /// any label at this position, i.e. `*`
+    Any,
+    /// a label that matches a glob or regex, e.g. `"prod-*"` or `~"^app-"`
+    Pattern(Pattern),
+}
+
+impl Label {
+    fn matches(&self, block_label: &str) -> bool {
+        match self {
+            Label::Exact(label) => label == block_label,
+            Label::Any => true,
+            Label::Pattern(pattern) => pattern.is_match(block_label),
+        }
+    }
 }
 
 impl Field {
     pub fn new(name: &str) -> Self {
         Field {
             name: name.to_string(),
+            pattern: None,
             labels: Vec::new(),
+            label_prefix: false,
         }
     }
 
     pub fn labeled(name: &str, labels: &[&str]) -> Self {
         Field {
             name: name.to_string(),
-            labels: labels.iter().map(|label| label.to_string()).collect(),
+            pattern: None,
+            labels: labels
+                .iter()
+                .map(|label| Label::Exact(label.to_string()))
+                .collect(),
+            label_prefix: false,
+        }
+    }
+
+    /// whether this field's name (or pattern) matches `name`
+    fn matches_name(&self, name: &str) -> bool {
+        match &self.pattern {
+            Some(pattern) => pattern.is_match(name),
+            None => self.name == name,
+        }
+    }
+
+    /// whether this field selects the attribute `key` of a body
+    pub(crate) fn matches_attr(&self, key: &str) -> bool {
+        self.matches_name(key)
+    }
+
+    /// whether this field selects the block `ident` (with `labels`) of a body
+    pub(crate) fn matches_block<'a>(
+        &self,
+        ident: &str,
+        labels: impl IntoIterator<Item = &'a str>,
+    ) -> bool {
+        if !self.matches_name(ident) {
+            return false;
+        }
+        // a field without labels matches blocks with any labels
+        if self.labels.is_empty() {
+            return true;
+        }
+        let block_labels: Vec<_> = labels.into_iter().collect();
+        let count_matches = if self.label_prefix {
+            block_labels.len() >= self.labels.len()
+        } else {
+            block_labels.len() == self.labels.len()
+        };
+        count_matches
+            && self
+                .labels
+                .iter()
+                .zip(block_labels)
+                .all(|(label, block_label)| label.matches(block_label))
+    }
+
+    /// whether this field selects the value at `key` of an object
+    pub(crate) fn matches_object_key(&self, key: Option<&str>) -> bool {
+        key.is_some_and(|key| self.matches_name(key))
+    }
+
+    /// how many of the `count` labels of a block that this field matches it
+    /// does not name, which `.*` steps over (e.g. both labels of a resource
+    /// for `.resource`, so that `.resource.*.*` is every resource)
+    pub(crate) fn unnamed_labels(&self, count: usize) -> usize {
+        if self.labels.is_empty() || self.label_prefix {
+            count.saturating_sub(self.labels.len())
+        } else {
+            0
+        }
+    }
+}
+
+/// the condition of a [`Segment::Select`]
+///
+/// e.g. for the filter `'.variable.select(.sensitive == true)'` the predicate
+/// is the filter `.sensitive == true`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Predicate {
+    /// the filter that is evaluated against each result
+    ///
+    /// a result is kept if `filter` has any result other than `false` or
+    /// `null` (a filter that fails is the same as one without results)
+    pub filter: Filter,
+}
+
+impl From<Filter> for Predicate {
+    fn from(filter: Filter) -> Self {
+        Predicate { filter }
+    }
+}
+
+/// what a [`Segment::Kind`] keeps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    /// `:attr`, attributes (and other values, e.g. of an object) but not
+    /// blocks
+    Attr,
+    /// `:block`
+    Block,
+    /// `:object`
+    Object,
+    /// `:list`
+    List,
+}
+
+/// a comparison operator of a [`Filter::Compare`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `>`
+    Gt,
+    /// `>=`
+    Ge,
+    /// `contains` (a substring, an array element or an object key)
+    Contains,
+}
+
+/// an arithmetic operator of a [`Filter::Arithmetic`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    /// `+` (numbers, or concatenating strings or arrays, or merging objects)
+    Add,
+    /// `-` (numbers, or removing elements from an array)
+    Subtract,
+    /// `*`
+    Multiply,
+    /// `/`
+    Divide,
+    /// `%`
+    Remainder,
+}
+
+impl Segment {
+    /// whether this segment only narrows down what the segment before it
+    /// matches, i.e. [`Segment::Select`] and [`Segment::Kind`]
+    pub(crate) fn is_qualifier(&self) -> bool {
+        matches!(self, Segment::Select(_) | Segment::Kind(_))
+    }
+}
+
+/// the qualifiers (see [`Segment::is_qualifier`]) that directly follow the
+/// segment at `index`
+pub(crate) fn following_qualifiers(
+    segments: &[Segment],
+    index: usize,
+) -> impl Iterator<Item = &Segment> {
+    segments
+        .iter()
+        .skip(index + 1)
+        .take_while(|segment| segment.is_qualifier())
+}
+
+/// remove the [`Segment::Optional`]s from `segments`, along with whether each
+/// of the remaining segments was marked as optional (e.g. `[5]?`)
+pub(crate) fn without_optional(segments: Vec<Segment>) -> (Vec<Segment>, Vec<bool>) {
+    let mut remaining = Vec::with_capacity(segments.len());
+    let mut optional: Vec<bool> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        if matches!(segment, Segment::Optional) {
+            // `?` follows the qualifiers of the segment it marks
+            if let Some(marked) = remaining.iter().rposition(|s: &Segment| !s.is_qualifier()) {
+                optional[marked] = true;
+            }
+        } else {
+            remaining.push(segment);
+            optional.push(false);
         }
     }
+    (remaining, optional)
+}
+
+/// resolve a (possibly negative) [`Segment::Index`] against an array of
+/// length `len`, returning `None` if it is out of bounds
+pub(crate) fn resolve_index(index: isize, len: usize) -> Option<usize> {
+    let index = if index < 0 {
+        len.checked_sub(index.unsigned_abs())?
+    } else {
+        index as usize
+    };
+    if index < len {
+        Some(index)
+    } else {
+        None
+    }
 }
 
-/// parse `input` and return a vector of [`Field`]s
+/// resolve the (possibly negative, possibly open) bounds of a
+/// [`Segment::Slice`] against an array of length `len`
 ///
-/// a valid filter is one or more chained segments
-pub fn parse_filter(input: &str) -> Result<Vec<Field>, Box<FilterError<Rule>>> {
-    let mut fields = Vec::new();
-    let pairs = Filter::parse(Rule::filter, input)?;
+/// out of bounds values are clamped, the same way `jq` does
+pub(crate) fn resolve_slice(start: Option<isize>, end: Option<isize>, len: usize) -> Range<usize> {
+    let clamp = |bound: isize| {
+        if bound < 0 {
+            len.saturating_sub(bound.unsigned_abs())
+        } else {
+            (bound as usize).min(len)
+        }
+    };
+    let start = start.map_or(0, clamp);
+    let end = end.map_or(len, clamp);
+    start..end.max(start)
+}
+
+/// parse `input` and return a [`Filter`]
+///
+/// a valid filter is one or more chained segments (or the identity filter
+/// `.`), optionally combined with other filters using `|` and `,` (and
+/// grouped with parentheses)
+pub fn parse_filter(input: &str) -> Result<Filter, Box<FilterError<Rule>>> {
+    parse_filter_with_variables(input, &Variables::new())
+}
+
+/// the values of the variables of a filter (e.g. `$name`), by name
+///
+/// `$ENV` (unless it is given a value here) is an object of the environment
+/// variables
+pub type Variables = HashMap<String, Value>;
+
+/// parse `input` like [`parse_filter`], replacing each variable (e.g. `$name`
+/// or `$ENV.HOME`) with its value
+///
+/// a variable can be used as a value (e.g. `select(.default == $name)`), a
+/// name (`.[$name]`) or a label (`.module{$name}`)
+///
+/// ```
+/// use hq_rs::parser::{parse_filter, parse_filter_with_variables, Variables};
+///
+/// let variables = Variables::from([(String::from("name"), "with \"quotes\"".into())]);
+/// let filter = parse_filter_with_variables(".module{$name}.version", &variables).unwrap();
+/// assert_eq!(parse_filter(r#".module{"with \"quotes\""}.version"#).unwrap(), filter);
+/// ```
+pub fn parse_filter_with_variables(
+    input: &str,
+    variables: &Variables,
+) -> Result<Filter, Box<FilterError<Rule>>> {
+    let error = |error| Box::new(filter_error(input, error));
+    let mut pairs = FilterParser::parse(Rule::filter, input).map_err(error)?;
+    // the grammar requires exactly one pipe at the top level
+    parse_pipe(pairs.next().unwrap(), variables).map_err(error)
+}
+
+/// a diagnostic for `error`, with help for some common mistakes
+fn filter_error(input: &str, error: ParseError) -> FilterError<Rule> {
+    let mut help = Vec::new();
+    if let ErrorVariant::ParsingError { positives, .. } = &error.variant {
+        let pos = match error.location {
+            InputLocation::Pos(pos) | InputLocation::Span((pos, _)) => pos,
+        };
+        let (before, rest) = input.split_at(pos);
+        let word: String = rest
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '-'))
+            .collect();
+        let starts_word = rest.starts_with(|c: char| c.is_alphabetic() || c == '_');
+        if input.trim().is_empty() {
+            help.push(String::from("the filter `.` returns the whole document"));
+        } else if rest.starts_with('=') && !rest.starts_with("==") {
+            help.push(String::from(
+                "`=` only sets a value with `hq write`, values are compared with `==`",
+            ));
+        } else if starts_word && positives.contains(&Rule::label_expr) {
+            help.push(format!("labels are quoted, e.g. `{{\"{word}\"}}`"));
+        } else if starts_word && positives.contains(&Rule::multiplicative) && !before.ends_with('.')
+        {
+            help.push(format!("paths start with a `.`, e.g. `.{word}`"));
+        }
+    }
+    help.into_iter().fold(
+        FilterError::new(error, |rule| describe_rule(rule).map(String::from)),
+        FilterError::with_help,
+    )
+}
+
+/// what a rule matches, as it is shown in a [`FilterError`]
+fn describe_rule(rule: &Rule) -> Option<&'static str> {
+    match rule {
+        Rule::EOI => Some("the end of the filter"),
+        Rule::pipe
+        | Rule::comma
+        | Rule::alternative
+        | Rule::or_expr
+        | Rule::and_expr
+        | Rule::compare
+        | Rule::additive
+        | Rule::multiplicative
+        | Rule::path => Some("a filter"),
+        Rule::or_op | Rule::and_op | Rule::add_op | Rule::mul_op | Rule::comparison => {
+            Some("an operator")
+        }
+        Rule::field | Rule::recursive | Rule::wildcard | Rule::predicate => Some("`.`"),
+        Rule::iterate | Rule::index | Rule::slice => Some("`[`"),
+        Rule::optional => Some("`?`"),
+        Rule::kind => Some("`:`"),
+        Rule::kind_name => Some("`attr`, `block`, `object` or `list`"),
+        Rule::name | Rule::var_name => Some("a name"),
+        Rule::quoted_name => Some("a quoted name"),
+        Rule::label_expr => Some("a quoted label"),
+        Rule::label_regex | Rule::regex_text => Some("a regex"),
+        Rule::label_any => Some("`*`"),
+        Rule::label_rest => Some("`...`"),
+        Rule::variable | Rule::name_var | Rule::label_var => Some("a variable"),
+        Rule::integer | Rule::slice_start | Rule::slice_end => Some("an index"),
+        Rule::string | Rule::text | Rule::sq_text | Rule::template => Some("a string"),
+        Rule::interpolation => Some("`\\(`"),
+        Rule::array | Rule::object => Some("a filter"),
+        Rule::object_entry | Rule::key_name => Some("a key"),
+        Rule::number => Some("a number"),
+        Rule::boolean => Some("`true` or `false`"),
+        Rule::null => Some("`null`"),
+        // e.g. the case-insensitive flag, which is optional, so it is never
+        // the only thing that could follow
+        _ => None,
+    }
+}
+
+/// an error for a part of the filter that is valid according to the grammar
+/// but cannot be used (e.g. an invalid regex)
+type ParseError = Error<Rule>;
+
+fn parse_pipe(pair: Pair<Rule>, variables: &Variables) -> Result<Filter, ParseError> {
+    let mut filters = pair
+        .into_inner()
+        .map(|inner| parse_comma(inner, variables))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(if filters.len() == 1 {
+        filters.remove(0)
+    } else {
+        Filter::Pipe(filters)
+    })
+}
+
+fn parse_comma(pair: Pair<Rule>, variables: &Variables) -> Result<Filter, ParseError> {
+    let mut filters = pair
+        .into_inner()
+        .map(|inner| parse_alternative(inner, variables))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(if filters.len() == 1 {
+        filters.remove(0)
+    } else {
+        Filter::Comma(filters)
+    })
+}
+
+fn parse_alternative(pair: Pair<Rule>, variables: &Variables) -> Result<Filter, ParseError> {
+    let mut filters = pair
+        .into_inner()
+        .map(|inner| parse_or(inner, variables))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(if filters.len() == 1 {
+        filters.remove(0)
+    } else {
+        Filter::Alternative(filters)
+    })
+}
+
+fn parse_or(pair: Pair<Rule>, variables: &Variables) -> Result<Filter, ParseError> {
+    let mut filters = pair
+        .into_inner()
+        .filter(|inner| inner.as_rule() != Rule::or_op)
+        .map(|inner| parse_and(inner, variables))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(if filters.len() == 1 {
+        filters.remove(0)
+    } else {
+        Filter::Or(filters)
+    })
+}
+
+fn parse_and(pair: Pair<Rule>, variables: &Variables) -> Result<Filter, ParseError> {
+    let mut filters = pair
+        .into_inner()
+        .filter(|inner| inner.as_rule() != Rule::and_op)
+        .map(|inner| parse_compare(inner, variables))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(if filters.len() == 1 {
+        filters.remove(0)
+    } else {
+        Filter::And(filters)
+    })
+}
+
+fn parse_compare(pair: Pair<Rule>, variables: &Variables) -> Result<Filter, ParseError> {
+    let mut inner = pair.into_inner();
+    // the grammar requires an operand, optionally followed by a comparison
+    // and another operand
+    let left = parse_arithmetic(inner.next().unwrap(), variables)?;
+    Ok(match (inner.next(), inner.next()) {
+        (Some(comparison), Some(right)) => Filter::Compare(
+            Box::new(left),
+            parse_comparison(comparison),
+            Box::new(parse_arithmetic(right, variables)?),
+        ),
+        _ => left,
+    })
+}
+
+fn parse_comparison(pair: Pair<Rule>) -> Comparison {
+    match pair.as_str() {
+        "==" => Comparison::Eq,
+        "!=" => Comparison::Ne,
+        "<" => Comparison::Lt,
+        "<=" => Comparison::Le,
+        ">" => Comparison::Gt,
+        ">=" => Comparison::Ge,
+        "contains" => Comparison::Contains,
+        comparison => unreachable!("{comparison} is not a comparison"),
+    }
+}
+
+/// an `additive` or `multiplicative` rule, whose operators are applied from
+/// left to right
+fn parse_arithmetic(pair: Pair<Rule>, variables: &Variables) -> Result<Filter, ParseError> {
+    let mut inner = pair.into_inner();
+    // the grammar requires an operand first, and one after each operator
+    let first = inner.next().unwrap();
+    let mut filter = parse_operand(first, variables)?;
+    while let (Some(operator), Some(operand)) = (inner.next(), inner.next()) {
+        let operator = match operator.as_str() {
+            "+" => Operator::Add,
+            "-" => Operator::Subtract,
+            "*" => Operator::Multiply,
+            "/" => Operator::Divide,
+            "%" => Operator::Remainder,
+            operator => unreachable!("{operator} is not an operator"),
+        };
+        filter = Filter::Arithmetic(
+            Box::new(filter),
+            operator,
+            Box::new(parse_operand(operand, variables)?),
+        );
+    }
+    Ok(filter)
+}
+
+fn parse_operand(pair: Pair<Rule>, variables: &Variables) -> Result<Filter, ParseError> {
+    match pair.as_rule() {
+        Rule::multiplicative => parse_arithmetic(pair, variables),
+        _ => parse_term(pair, variables),
+    }
+}
+
+fn parse_term(pair: Pair<Rule>, variables: &Variables) -> Result<Filter, ParseError> {
+    Ok(match pair.as_rule() {
+        Rule::pipe => parse_pipe(pair, variables)?,
+        Rule::path => Filter::Path(parse_segments(pair.into_inner(), variables)?),
+        Rule::template => Filter::Template(parse_template(pair, variables)?),
+        Rule::array => {
+            // the grammar requires a filter between the brackets
+            let pipe = pair.into_inner().next().unwrap();
+            Filter::Array(Box::new(parse_pipe(pipe, variables)?))
+        }
+        Rule::object => Filter::Object(parse_object(pair, variables)?),
+        Rule::string | Rule::number | Rule::boolean | Rule::null => {
+            Filter::Literal(parse_literal(pair)?)
+        }
+        Rule::variable => Filter::Literal(parse_variable(&pair, variables)?),
+        _ => Filter::Builtin(parse_builtin(pair, variables)?),
+    })
+}
+
+fn parse_builtin(pair: Pair<Rule>, variables: &Variables) -> Result<Builtin, ParseError> {
+    Ok(match pair.as_rule() {
+        Rule::has => {
+            // the grammar requires a string or integer argument
+            let argument = pair.into_inner().next().unwrap();
+            match argument.as_rule() {
+                Rule::string => Builtin::Has(parse_literal(argument)?),
+                _ => Builtin::Has(parse_number(&argument)?),
+            }
+        }
+        Rule::keys => Builtin::Keys,
+        Rule::labels_fn => Builtin::Labels,
+        Rule::ident => Builtin::Ident,
+        Rule::length => Builtin::Length,
+        Rule::type_name => Builtin::Type,
+        Rule::to_entries => Builtin::ToEntries,
+        Rule::not => Builtin::Not,
+        Rule::parent => Builtin::Parent,
+        Rule::ancestors => Builtin::Ancestors,
+        Rule::sort_by | Rule::group_by | Rule::map => {
+            let rule = pair.as_rule();
+            // the grammar requires a filter between the parentheses
+            let pipe = pair.into_inner().next().unwrap();
+            let filter = Box::new(parse_pipe(pipe, variables)?);
+            match rule {
+                Rule::sort_by => Builtin::SortBy(filter),
+                Rule::group_by => Builtin::GroupBy(filter),
+                _ => Builtin::Map(filter),
+            }
+        }
+        Rule::sort => Builtin::Sort,
+        Rule::unique => Builtin::Unique,
+        Rule::min => Builtin::Min,
+        Rule::max => Builtin::Max,
+        Rule::add => Builtin::Add,
+        Rule::split => Builtin::Split(parse_string_argument(pair)?),
+        Rule::join => Builtin::Join(parse_string_argument(pair)?),
+        Rule::test => Builtin::Test(parse_regex_argument(pair)?),
+        Rule::capture => Builtin::Capture(parse_regex_argument(pair)?),
+        Rule::startswith => Builtin::StartsWith(parse_string_argument(pair)?),
+        Rule::endswith => Builtin::EndsWith(parse_string_argument(pair)?),
+        Rule::ltrimstr => Builtin::LtrimStr(parse_string_argument(pair)?),
+        Rule::ascii_downcase => Builtin::AsciiDowncase,
+        rule => unreachable!("{rule:?} is not a builtin"),
+    })
+}
+
+fn parse_segments(pairs: Pairs<Rule>, variables: &Variables) -> Result<Vec<Segment>, ParseError> {
+    let mut segments = Vec::new();
     for pair in pairs {
-        let mut name = String::new();
-        let mut labels = Vec::new();
-
-        let inner_pairs = pair.into_inner();
-        for inner in inner_pairs {
-            match inner.as_rule() {
-                Rule::name => {
-                    // according to clippy, this is a more efficient way of doing
-                    // `name = inner.as_str().to_owned()`
-                    inner.as_str().clone_into(&mut name);
+        match pair.as_rule() {
+            Rule::field => {
+                segments.push(Segment::Field(parse_field(pair, variables)?));
+            }
+            Rule::index => {
+                // the grammar requires an integer between the brackets
+                let index = pair.into_inner().map(parse_integer).next().unwrap();
+                segments.push(Segment::Index(index));
+            }
+            Rule::recursive => {
+                // the grammar requires a field after the `..`
+                let field = pair
+                    .into_inner()
+                    .map(|inner| parse_field(inner, variables))
+                    .next()
+                    .unwrap()?;
+                segments.push(Segment::Recursive(field));
+            }
+            Rule::wildcard => {
+                segments.push(Segment::Wildcard);
+            }
+            Rule::iterate => {
+                segments.push(Segment::Iterate);
+            }
+            Rule::slice => {
+                let mut inner_pairs = pair.into_inner();
+                let start = inner_pairs.next().and_then(parse_bound);
+                let end = inner_pairs.next().and_then(parse_bound);
+                segments.push(Segment::Slice(start, end));
+            }
+            Rule::predicate => {
+                segments.push(Segment::Select(parse_predicate(pair, variables)?));
+            }
+            Rule::kind => {
+                // the grammar requires a kind after the `:`
+                let kind = match pair.into_inner().next().unwrap().as_str() {
+                    "attr" => Kind::Attr,
+                    "block" => Kind::Block,
+                    "object" => Kind::Object,
+                    _ => Kind::List,
+                };
+                segments.push(Segment::Kind(kind));
+            }
+            Rule::optional => {
+                segments.push(Segment::Optional);
+            }
+            _ => {}
+        }
+    }
+    Ok(segments)
+}
+
+fn parse_field(pair: Pair<Rule>, variables: &Variables) -> Result<Field, ParseError> {
+    let mut name = String::new();
+    let mut pattern = None;
+    let mut labels = Vec::new();
+    let mut label_prefix = false;
+
+    let inner_pairs = pair.into_inner();
+    for inner in inner_pairs {
+        match inner.as_rule() {
+            Rule::name => {
+                // according to clippy, this is a more efficient way of doing
+                // `name = inner.as_str().to_owned()`
+                inner.as_str().clone_into(&mut name);
+                if Pattern::is_glob(&name) {
+                    pattern = Some(Pattern::glob(&name, false));
                 }
-                Rule::quoted_name => {
-                    inner.as_str().clone_into(&mut name);
+            }
+            Rule::quoted_name => {
+                // the grammar requires the text between the quotes
+                let quoted = parse_quoted(&inner.into_inner().next().unwrap())?;
+                if quoted.is_glob {
+                    pattern = Some(Pattern::glob(&quoted.glob, false));
+                    name = quoted.glob;
+                } else {
+                    name = quoted.text;
                 }
-                Rule::label => {
-                    labels.push(inner.as_str().to_owned());
+            }
+            Rule::name_var => {
+                // the grammar requires the variable between the brackets
+                let variable = inner.into_inner().next().unwrap();
+                name = variable_text(&variable, variables, "name")?;
+            }
+            Rule::regex_text => {
+                name = regex_source(&inner);
+                pattern = Some(parse_regex(&inner, &name, false)?);
+            }
+            Rule::case_flag => {
+                // the flag follows the name, so its pattern is rebuilt
+                pattern = Some(match pattern {
+                    Some(Pattern { is_regex: true, .. }) => parse_regex(&inner, &name, true)?,
+                    Some(pattern) => Pattern::glob(&pattern.source, true),
+                    None => {
+                        // without wildcards, the name is matched as a glob
+                        // that has none
+                        name = escape_glob(&name);
+                        Pattern::glob(&name, true)
+                    }
+                });
+            }
+            Rule::label_expr => {
+                let mut label_pairs = inner.into_inner();
+                // the grammar requires the label's text (even if empty)
+                let label = parse_quoted(&label_pairs.next().unwrap())?;
+                let case_insensitive = label_pairs.next().is_some();
+                labels.push(if case_insensitive || label.is_glob {
+                    Label::Pattern(Pattern::glob(&label.glob, case_insensitive))
+                } else {
+                    Label::Exact(label.text)
+                });
+            }
+            Rule::label_regex => {
+                let mut label_pairs = inner.into_inner();
+                // the grammar requires the regex's text
+                let text = label_pairs.next().unwrap();
+                let case_insensitive = label_pairs.next().is_some();
+                let source = regex_source(&text);
+                labels.push(Label::Pattern(parse_regex(
+                    &text,
+                    &source,
+                    case_insensitive,
+                )?));
+            }
+            Rule::label_var => {
+                // the grammar requires the variable
+                let variable = inner.into_inner().next().unwrap();
+                let label = variable_text(&variable, variables, "label")?;
+                // the value is matched exactly, even if it contains a `*`
+                labels.push(Label::Exact(label));
+            }
+            Rule::label_any => {
+                labels.push(Label::Any);
+            }
+            Rule::label_rest => {
+                label_prefix = true;
+            }
+            _ => {}
+        }
+    }
+    Ok(Field {
+        name,
+        pattern,
+        labels,
+        label_prefix,
+    })
+}
+
+/// the text between the quotes of a string, quoted name or label
+struct Quoted {
+    /// the text with its escape sequences replaced
+    text: String,
+    /// the text as a glob, in which only the unescaped `*` and `?` are
+    /// wildcards
+    glob: String,
+    /// whether there are any wildcards in `glob`
+    is_glob: bool,
+}
+
+fn parse_quoted(pair: &Pair<Rule>) -> Result<Quoted, ParseError> {
+    let mut quoted = Quoted {
+        text: String::new(),
+        glob: String::new(),
+        is_glob: false,
+    };
+    let mut chars = pair.as_str().chars();
+    while let Some(c) = chars.next() {
+        if c == '*' || c == '?' {
+            quoted.text.push(c);
+            quoted.glob.push(c);
+            quoted.is_glob = true;
+            continue;
+        }
+        let c = if c == '\\' {
+            // the grammar only allows valid escape sequences
+            match chars.next().unwrap() {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                escape @ ('u' | 'U') => {
+                    let digits = if escape == 'u' { 4 } else { 8 };
+                    let hex: String = chars.by_ref().take(digits).collect();
+                    // the grammar only allows hex digits
+                    let code = u32::from_str_radix(&hex, 16).unwrap();
+                    char::from_u32(code).ok_or_else(|| {
+                        Error::new_from_span(
+                            ErrorVariant::CustomError {
+                                message: format!("invalid unicode character `\\{escape}{hex}`"),
+                            },
+                            pair.as_span(),
+                        )
+                    })?
                 }
-                _ => {}
+                c => c,
             }
+        } else {
+            c
+        };
+        quoted.text.push(c);
+        quoted.glob.push_str(&escape_glob(&c.to_string()));
+    }
+    Ok(quoted)
+}
+
+/// `text` as a glob without wildcards (see [`Pattern::glob`])
+fn escape_glob(text: &str) -> String {
+    let mut glob = String::new();
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '\\') {
+            glob.push('\\');
         }
-        if !name.is_empty() {
-            fields.push(Field { name, labels });
+        glob.push(c);
+    }
+    glob
+}
+
+/// the regex in `text`, where `\"` is an escaped quote
+fn regex_source(text: &Pair<Rule>) -> String {
+    text.as_str().replace("\\\"", "\"")
+}
+
+fn parse_regex(
+    pair: &Pair<Rule>,
+    source: &str,
+    case_insensitive: bool,
+) -> Result<Pattern, ParseError> {
+    Pattern::regex(source, case_insensitive).map_err(|err| {
+        // the regex crate's message points at the problem over several
+        // lines, the last of which describes it
+        let err = err.to_string();
+        let reason = err.lines().last().unwrap_or_default();
+        let reason = reason.strip_prefix("error: ").unwrap_or(reason);
+        Error::new_from_span(
+            ErrorVariant::CustomError {
+                message: format!("invalid regex: {reason}"),
+            },
+            pair.as_span(),
+        )
+    })
+}
+
+/// the string between the parentheses of a function, e.g. `"/"` of
+/// `split("/")`
+fn parse_string_argument(pair: Pair<Rule>) -> Result<String, ParseError> {
+    // the grammar requires a string argument, which has text between the
+    // quotes (even if empty)
+    let text = pair
+        .into_inner()
+        .next()
+        .unwrap()
+        .into_inner()
+        .next()
+        .unwrap();
+    Ok(parse_quoted(&text)?.text)
+}
+
+/// the regex between the parentheses of a function, which is either a regex
+/// (e.g. `~"^v[0-9]+"i`) or a string (like in `jq`, e.g. `"^v[0-9]+"`)
+fn parse_regex_argument(pair: Pair<Rule>) -> Result<Pattern, ParseError> {
+    let mut inner = pair.into_inner();
+    // the grammar requires an argument
+    let argument = inner.next().unwrap();
+    match argument.as_rule() {
+        Rule::regex_text => {
+            let source = regex_source(&argument);
+            parse_regex(&argument, &source, inner.next().is_some())
+        }
+        _ => {
+            let text = argument.clone().into_inner().next().unwrap();
+            let source = parse_quoted(&text)?.text;
+            parse_regex(&argument, &source, false)
         }
     }
-    Ok(fields)
+}
+
+/// the text and interpolated filters of a string, e.g. `"\(.name)-logs"`
+fn parse_template(
+    pair: Pair<Rule>,
+    variables: &Variables,
+) -> Result<Vec<TemplatePart>, ParseError> {
+    let mut parts = Vec::new();
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::text => {
+                let text = parse_quoted(&inner)?.text;
+                if !text.is_empty() {
+                    parts.push(TemplatePart::Text(text));
+                }
+            }
+            _ => {
+                // the grammar requires a filter between the parentheses
+                let pipe = inner.into_inner().next().unwrap();
+                parts.push(TemplatePart::Interpolation(parse_pipe(pipe, variables)?));
+            }
+        }
+    }
+    Ok(parts)
+}
+
+/// the keys and values of an object, where a key without a value is the
+/// value of that key of the input (e.g. `{name}` is `{name: .name}`)
+fn parse_object(
+    pair: Pair<Rule>,
+    variables: &Variables,
+) -> Result<Vec<(String, Filter)>, ParseError> {
+    let mut entries = Vec::new();
+    for entry in pair.into_inner() {
+        let mut inner = entry.into_inner();
+        // the grammar requires a key
+        let key = inner.next().unwrap();
+        let key = match key.as_rule() {
+            // the grammar requires text between the quotes (even if empty)
+            Rule::string => parse_quoted(&key.into_inner().next().unwrap())?.text,
+            _ => key.as_str().to_owned(),
+        };
+        let value = match inner.next() {
+            Some(value) => parse_alternative(value, variables)?,
+            None => Filter::Path(vec![Field::new(&key).into()]),
+        };
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+fn parse_predicate(pair: Pair<Rule>, variables: &Variables) -> Result<Predicate, ParseError> {
+    // the grammar requires a filter between the parentheses
+    let pipe = pair.into_inner().next().unwrap();
+    Ok(Predicate::from(parse_pipe(pipe, variables)?))
+}
+
+/// the value of a variable, e.g. `$name` or `$ENV.HOME` (a key that an
+/// object does not have is `null`)
+fn parse_variable(pair: &Pair<Rule>, variables: &Variables) -> Result<Value, ParseError> {
+    let mut names = pair.clone().into_inner();
+    // the grammar requires the variable's name
+    let name = names.next().unwrap().as_str();
+    let mut value = match variables.get(name) {
+        Some(value) => value.clone(),
+        None if name == "ENV" => {
+            Value::Object(std::env::vars().map(|(k, v)| (k, v.into())).collect())
+        }
+        None => {
+            return Err(Error::new_from_span(
+                ErrorVariant::CustomError {
+                    message: format!("`${name}` is not defined"),
+                },
+                pair.as_span(),
+            ))
+        }
+    };
+    for key in names {
+        value = match value {
+            Value::Object(mut object) => object.swap_remove(key.as_str()).unwrap_or(Value::Null),
+            _ => {
+                return Err(Error::new_from_span(
+                    ErrorVariant::CustomError {
+                        message: format!("`{}` is not an object", pair.as_str()),
+                    },
+                    pair.as_span(),
+                ))
+            }
+        };
+    }
+    Ok(value)
+}
+
+/// the value of a variable that is used as a name or label, which has to be
+/// a string
+fn variable_text(
+    pair: &Pair<Rule>,
+    variables: &Variables,
+    usage: &str,
+) -> Result<String, ParseError> {
+    match parse_variable(pair, variables)? {
+        Value::String(text) => Ok(text),
+        value => Err(Error::new_from_span(
+            ErrorVariant::CustomError {
+                message: format!(
+                    "`{}` is {value}, but a {usage} has to be a string",
+                    pair.as_str()
+                ),
+            },
+            pair.as_span(),
+        )),
+    }
+}
+
+fn parse_literal(pair: Pair<Rule>) -> Result<Value, ParseError> {
+    Ok(match pair.as_rule() {
+        Rule::string => {
+            // the grammar requires text between the quotes (even if empty)
+            let text = pair.into_inner().next().unwrap();
+            Value::String(parse_quoted(&text)?.text)
+        }
+        Rule::number => parse_number(&pair)?,
+        Rule::boolean => Value::Bool(pair.as_str() == "true"),
+        _ => Value::Null,
+    })
+}
+
+fn parse_number(pair: &Pair<Rule>) -> Result<Value, ParseError> {
+    let number = pair.as_str();
+    if let Ok(integer) = number.parse::<i64>() {
+        return Ok(Value::from(integer));
+    }
+    if let Ok(integer) = number.parse::<u64>() {
+        return Ok(Value::from(integer));
+    }
+    // the grammar only allows valid (but possibly very large) numbers
+    let float: f64 = number.parse().unwrap();
+    // a number without a fraction is an integer, which would be clamped to
+    // the range of an `i64` instead of staying a float
+    let in_range = (i64::MIN as f64..i64::MAX as f64).contains(&float);
+    match Number::from_f64(float) {
+        Some(number) if float.fract() != 0.0 || in_range => Ok(Value::Number(number)),
+        _ => Err(Error::new_from_span(
+            ErrorVariant::CustomError {
+                message: format!("`{number}` is too large for a number"),
+            },
+            pair.as_span(),
+        )),
+    }
+}
+
+fn parse_bound(pair: Pair<Rule>) -> Option<isize> {
+    pair.into_inner().map(parse_integer).next()
+}
+
+fn parse_integer(pair: Pair<Rule>) -> isize {
+    let integer = pair.as_str();
+    // an index this large is out of bounds either way, so saturate instead
+    // of failing on overflow
+    integer.parse().unwrap_or(if integer.starts_with('-') {
+        isize::MIN
+    } else {
+        isize::MAX
+    })
 }
 
 #[cfg(test)]
@@ -78,9 +1283,9 @@ mod tests {
     #[test]
     fn name_filter() {
         let input = ".a_name";
-        let expected = vec![Field::new("a_name")];
-        let fields = parse_filter(input).expect("parse error");
-        assert_eq!(expected, fields);
+        let expected: Vec<Segment> = vec![Field::new("a_name").into()];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
     }
 
     #[test]
@@ -93,20 +1298,694 @@ mod tests {
     #[test]
     fn label_filter() {
         let input = ".a_name{\"a_label\"}";
-        let expected = vec![Field::labeled("a_name", &["a_label"])];
-        let fields = parse_filter(input).expect("parse error");
-        assert_eq!(expected, fields);
+        let expected: Vec<Segment> = vec![Field::labeled("a_name", &["a_label"]).into()];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
     }
 
     #[test]
     fn traversal_filter() {
         let input = ".a_name{\"a_label\"}.another_name{\"another_label\"}.third_name";
+        let expected: Vec<Segment> = vec![
+            Field::labeled("a_name", &["a_label"]).into(),
+            Field::labeled("another_name", &["another_label"]).into(),
+            Field::new("third_name").into(),
+        ];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn positional_label_filter() {
+        let input = ".a_name{*, \"a_label\", ...}";
+        let expected: Vec<Segment> = vec![Field {
+            name: String::from("a_name"),
+            pattern: None,
+            labels: vec![Label::Any, Label::Exact(String::from("a_label"))],
+            label_prefix: true,
+        }
+        .into()];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn positional_label_match() {
+        let field = Field::labeled("data", &["aws_ami", "ubuntu"]);
+        assert!(field.matches_block("data", ["aws_ami", "ubuntu"]));
+        assert!(!field.matches_block("data", ["aws_ami", "other"]));
+        assert!(!field.matches_block("data", ["ubuntu", "aws_ami"]));
+        assert!(!field.matches_block("data", ["aws_ami"]));
+
+        let field = Field {
+            labels: vec![Label::Any, Label::Exact(String::from("ubuntu"))],
+            ..Field::new("data")
+        };
+        assert!(field.matches_block("data", ["x", "ubuntu"]));
+        assert!(!field.matches_block("data", ["x", "ubuntu", "y"]));
+
+        let field = Field {
+            label_prefix: true,
+            ..Field::labeled("data", &["aws_ami"])
+        };
+        assert!(field.matches_block("data", ["aws_ami", "ubuntu"]));
+        assert!(!field.matches_block("data", ["x", "aws_ami"]));
+    }
+
+    #[test]
+    fn pattern_filter() {
+        let input = ".aws_*{\"prod-*\", ~\"^app-[0-9]+$\"i}";
+        let expected: Vec<Segment> = vec![Field {
+            name: String::from("aws_*"),
+            pattern: Some(Pattern::glob("aws_*", false)),
+            labels: vec![
+                Label::Pattern(Pattern::glob("prod-*", false)),
+                Label::Pattern(Pattern::regex("^app-[0-9]+$", true).unwrap()),
+            ],
+            label_prefix: false,
+        }
+        .into()];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn case_insensitive_filter() {
+        let filter = parse_filter(".[\"Name\"i]{\"web\"i}").expect("parse error");
+        let Filter::Path(segments) = filter else {
+            panic!("expected a path");
+        };
+        let Segment::Field(field) = &segments[0] else {
+            panic!("expected a field");
+        };
+        assert!(field.matches_attr("NAME"));
+        assert!(field.matches_block("name", ["WEB"]));
+        assert!(!field.matches_block("name", ["web-1"]));
+    }
+
+    #[test]
+    fn regex_name_filter() {
+        let filter = parse_filter(".~\"^aws_(s3|iam)_\"").expect("parse error");
+        let Filter::Path(segments) = filter else {
+            panic!("expected a path");
+        };
+        assert!(segments[0].matches_attr("aws_s3_bucket"));
+        assert!(!segments[0].matches_attr("aws_instance"));
+    }
+
+    #[test]
+    fn invalid_regex_filter() {
+        assert!(parse_filter(".a{~\"(\"}").is_err());
+    }
+
+    #[test]
+    fn hcl_identifier_filter() {
+        let input = ".cool-module.名前._x";
+        let expected: Vec<Segment> = vec![
+            Field::new("cool-module").into(),
+            Field::new("名前").into(),
+            Field::new("_x").into(),
+        ];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn escaped_label_filter() {
+        let input = r#".module{"registry.example.com/team/vpc", "say \"hi\"\té", 'it\'s', ""}"#;
+        let expected: Vec<Segment> = vec![Field::labeled(
+            "module",
+            &["registry.example.com/team/vpc", "say \"hi\"\té", "it's", ""],
+        )
+        .into()];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn escaped_glob_filter() {
+        let input = r#".["a*b"]{"star\*", "\*\?"}"#;
+        let expected: Vec<Segment> = vec![Field {
+            name: String::from("a*b"),
+            pattern: Some(Pattern::glob("a*b", false)),
+            labels: vec![
+                Label::Exact(String::from("star*")),
+                Label::Exact(String::from("*?")),
+            ],
+            label_prefix: false,
+        }
+        .into()];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn invalid_escape_filter() {
+        assert!(parse_filter(r#".a{"\x"}"#).is_err());
+        assert!(parse_filter(r#".a{"\ud800"}"#).is_err());
+    }
+
+    #[test]
+    fn index_filter() {
+        let input = ".a_name[0][-1]";
+        let expected = vec![
+            Field::new("a_name").into(),
+            Segment::Index(0),
+            Segment::Index(-1),
+        ];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn slice_filter() {
+        let input = ".a_name[1:3][:-1][2:]";
+        let expected = vec![
+            Field::new("a_name").into(),
+            Segment::Slice(Some(1), Some(3)),
+            Segment::Slice(None, Some(-1)),
+            Segment::Slice(Some(2), None),
+        ];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn identity_filter() {
+        let filter = parse_filter(".").expect("parse error");
+        assert_eq!(Filter::Path(vec![]), filter);
+    }
+
+    #[test]
+    fn wildcard_filter() {
+        let input = ".resource.*.tags[]";
+        let expected = vec![
+            Field::new("resource").into(),
+            Segment::Wildcard,
+            Field::new("tags").into(),
+            Segment::Iterate,
+        ];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn recursive_filter() {
+        let input = "..tags.Name";
+        let expected = vec![
+            Segment::Recursive(Field::new("tags")),
+            Field::new("Name").into(),
+        ];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn predicate_filter() {
+        let input = ".variable.select(.sensitive == true).select(.default)";
+        let expected = vec![
+            Field::new("variable").into(),
+            Segment::Select(Predicate::from(Filter::Compare(
+                Box::new(Filter::Path(vec![Field::new("sensitive").into()])),
+                Comparison::Eq,
+                Box::new(Filter::Literal(Value::Bool(true))),
+            ))),
+            Segment::Select(Predicate::from(Filter::Path(vec![
+                Field::new("default").into()
+            ]))),
+        ];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn identity_predicate_filter() {
+        let input = ".ports[].select(. >= 1024)";
+        let expected = vec![
+            Field::new("ports").into(),
+            Segment::Iterate,
+            Segment::Select(Predicate::from(Filter::Compare(
+                Box::new(Filter::Path(vec![])),
+                Comparison::Ge,
+                Box::new(Filter::Literal(Value::from(1024))),
+            ))),
+        ];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn pipe_filter() {
+        let input = ".variable | .default";
+        let expected = Filter::Pipe(vec![
+            Filter::Path(vec![Field::new("variable").into()]),
+            Filter::Path(vec![Field::new("default").into()]),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn comma_filter() {
+        // `,` binds tighter than `|`
+        let input = ".variable | .default, .type";
+        let expected = Filter::Pipe(vec![
+            Filter::Path(vec![Field::new("variable").into()]),
+            Filter::Comma(vec![
+                Filter::Path(vec![Field::new("default").into()]),
+                Filter::Path(vec![Field::new("type").into()]),
+            ]),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn grouped_filter() {
+        let input = "(.a | .b),.c";
+        let expected = Filter::Comma(vec![
+            Filter::Pipe(vec![
+                Filter::Path(vec![Field::new("a").into()]),
+                Filter::Path(vec![Field::new("b").into()]),
+            ]),
+            Filter::Path(vec![Field::new("c").into()]),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn builtin_filter() {
+        let input = ".variable | labels, has(\"default\"), has(0)";
+        let expected = Filter::Pipe(vec![
+            Filter::Path(vec![Field::new("variable").into()]),
+            Filter::Comma(vec![
+                Filter::Builtin(Builtin::Labels),
+                Filter::Builtin(Builtin::Has(Value::from("default"))),
+                Filter::Builtin(Builtin::Has(Value::from(0))),
+            ]),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn navigation_filter() {
+        let input = "..enabled | parent, ancestors";
+        let expected = Filter::Pipe(vec![
+            Filter::Path(vec![Segment::Recursive(Field::new("enabled"))]),
+            Filter::Comma(vec![
+                Filter::Builtin(Builtin::Parent),
+                Filter::Builtin(Builtin::Ancestors),
+            ]),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn collection_filter() {
+        let input = "sort_by(.source | length), map(.a, .b), sort";
+        let expected = Filter::Comma(vec![
+            Filter::Builtin(Builtin::SortBy(Box::new(Filter::Pipe(vec![
+                Filter::Path(vec![Field::new("source").into()]),
+                Filter::Builtin(Builtin::Length),
+            ])))),
+            Filter::Builtin(Builtin::Map(Box::new(Filter::Comma(vec![
+                Filter::Path(vec![Field::new("a").into()]),
+                Filter::Path(vec![Field::new("b").into()]),
+            ])))),
+            Filter::Builtin(Builtin::Sort),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+        // a name that starts with a function's name is not that function
+        assert!(parse_filter("sorted").is_err());
+    }
+
+    #[test]
+    fn string_filter() {
+        let input = r#"split("/"), test("^v[0-9]"), capture(~"ref=(?<ref>.+)"i), ascii_downcase"#;
+        let expected = Filter::Comma(vec![
+            Filter::Builtin(Builtin::Split(String::from("/"))),
+            Filter::Builtin(Builtin::Test(Pattern::regex("^v[0-9]", false).unwrap())),
+            Filter::Builtin(Builtin::Capture(
+                Pattern::regex("ref=(?<ref>.+)", true).unwrap(),
+            )),
+            Filter::Builtin(Builtin::AsciiDowncase),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn template_filter() {
+        let input = r#""\(.name)-\( .id | length )\n""#;
+        let expected = Filter::Template(vec![
+            TemplatePart::Interpolation(Filter::Path(vec![Field::new("name").into()])),
+            TemplatePart::Text(String::from("-")),
+            TemplatePart::Interpolation(Filter::Pipe(vec![
+                Filter::Path(vec![Field::new("id").into()]),
+                Filter::Builtin(Builtin::Length),
+            ])),
+            TemplatePart::Text(String::from("\n")),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+        // a string without interpolations is a literal
+        let filter = parse_filter(r#""\n""#).expect("parse error");
+        assert_eq!(Filter::Literal(Value::from("\n")), filter);
+    }
+
+    #[test]
+    fn construction_filter() {
+        let input = r#"{name: (.variable | labels), "a b": .x // 1, default,}, [.a, .b]"#;
+        let expected = Filter::Comma(vec![
+            Filter::Object(vec![
+                (
+                    String::from("name"),
+                    Filter::Pipe(vec![
+                        Filter::Path(vec![Field::new("variable").into()]),
+                        Filter::Builtin(Builtin::Labels),
+                    ]),
+                ),
+                (
+                    String::from("a b"),
+                    Filter::Alternative(vec![
+                        Filter::Path(vec![Field::new("x").into()]),
+                        Filter::Literal(Value::from(1)),
+                    ]),
+                ),
+                // a key without a value is that key of the input
+                (
+                    String::from("default"),
+                    Filter::Path(vec![Field::new("default").into()]),
+                ),
+            ]),
+            Filter::Array(Box::new(Filter::Comma(vec![
+                Filter::Path(vec![Field::new("a").into()]),
+                Filter::Path(vec![Field::new("b").into()]),
+            ]))),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+        // brackets with an index (or nothing) between them are a path
+        let filter = parse_filter("[0]").expect("parse error");
+        assert_eq!(Filter::Path(vec![Segment::Index(0)]), filter);
+    }
+
+    #[test]
+    fn builtin_name_filter() {
+        // a builtin's name is only a builtin on its own
+        assert!(parse_filter("keysx").is_err());
+        let expected: Vec<Segment> = vec![Field::new("keys").into()];
+        let filter = parse_filter(".keys").expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn optional_filter() {
+        let input = ".tags?.Name[0]?";
+        let expected = vec![
+            Field::new("tags").into(),
+            Segment::Optional,
+            Field::new("Name").into(),
+            Segment::Index(0),
+            Segment::Optional,
+        ];
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+    }
+
+    #[test]
+    fn kind_filter() {
+        let input = ".resource.lifecycle:block?.*:list";
         let expected = vec![
-            Field::labeled("a_name", &["a_label"]),
-            Field::labeled("another_name", &["another_label"]),
-            Field::new("third_name"),
+            Field::new("resource").into(),
+            Field::new("lifecycle").into(),
+            Segment::Kind(Kind::Block),
+            Segment::Optional,
+            Segment::Wildcard,
+            Segment::Kind(Kind::List),
         ];
-        let fields = parse_filter(input).expect("parse error");
-        assert_eq!(expected, fields);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(Filter::Path(expected), filter);
+
+        let err = parse_filter(".a:blocks").expect_err("unknown kind");
+        assert_eq!("expected `attr`, `block`, `object` or `list`", err.label());
+    }
+
+    #[test]
+    fn alternative_filter() {
+        // `//` binds tighter than `,`
+        let input = ".a // .b // \"none\", 1";
+        let expected = Filter::Comma(vec![
+            Filter::Alternative(vec![
+                Filter::Path(vec![Field::new("a").into()]),
+                Filter::Path(vec![Field::new("b").into()]),
+                Filter::Literal(Value::from("none")),
+            ]),
+            Filter::Literal(Value::from(1)),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn arithmetic_filter() {
+        // `*` binds tighter than `+`, and both are applied from left to right
+        let input = ".a + .b * 2 - 1";
+        let path = |name| Box::new(Filter::Path(vec![Field::new(name).into()]));
+        let expected = Filter::Arithmetic(
+            Box::new(Filter::Arithmetic(
+                path("a"),
+                Operator::Add,
+                Box::new(Filter::Arithmetic(
+                    path("b"),
+                    Operator::Multiply,
+                    Box::new(Filter::Literal(Value::from(2))),
+                )),
+            )),
+            Operator::Subtract,
+            Box::new(Filter::Literal(Value::from(1))),
+        );
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn arithmetic_without_whitespace() {
+        // a `*` followed by a term is multiplication rather than a glob
+        let path = |name| Box::new(Filter::Path(vec![Field::new(name).into()]));
+        let expected = Filter::Arithmetic(
+            path("min_size"),
+            Operator::Multiply,
+            Box::new(Filter::Literal(Value::from(2))),
+        );
+        assert_eq!(expected, parse_filter(".min_size*2").expect("parse error"));
+        let expected = Filter::Arithmetic(path("min_size"), Operator::Multiply, path("max_size"));
+        assert_eq!(
+            expected,
+            parse_filter(".min_size*.max_size").expect("parse error")
+        );
+        let filter = parse_filter(".min_*").expect("parse error");
+        let Filter::Path(segments) = filter else {
+            panic!("expected a path, got {filter:?}");
+        };
+        assert!(matches!(&segments[..], [Segment::Field(field)] if field.pattern.is_some()));
+
+        // a dash is part of a name, so a binary `-` needs whitespace
+        assert_eq!(
+            Filter::Path(vec![Field::new("min_size-1").into()]),
+            parse_filter(".min_size-1").expect("parse error")
+        );
+        let expected = Filter::Arithmetic(
+            path("min_size"),
+            Operator::Subtract,
+            Box::new(Filter::Literal(Value::from(1))),
+        );
+        assert_eq!(
+            expected,
+            parse_filter(".min_size - 1").expect("parse error")
+        );
+    }
+
+    #[test]
+    fn large_number() {
+        let expected = Filter::Literal(Value::from(u64::MAX));
+        assert_eq!(
+            expected,
+            parse_filter("18446744073709551615").expect("parse error")
+        );
+        assert_eq!(
+            Filter::Literal(Value::from(1000)),
+            parse_filter("1e3").expect("parse error")
+        );
+        let err = parse_filter(". == 100000000000000000000").expect_err("out of range");
+        assert!(
+            err.to_string()
+                .contains("`100000000000000000000` is too large for a number"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn boolean_filter() {
+        // `and` binds tighter than `or`, comparisons tighter than both
+        let input = ".a > 1 or .b and .c contains \"x\" | not";
+        let path = |name| Filter::Path(vec![Field::new(name).into()]);
+        let expected = Filter::Pipe(vec![
+            Filter::Or(vec![
+                Filter::Compare(
+                    Box::new(path("a")),
+                    Comparison::Gt,
+                    Box::new(Filter::Literal(Value::from(1))),
+                ),
+                Filter::And(vec![
+                    path("b"),
+                    Filter::Compare(
+                        Box::new(path("c")),
+                        Comparison::Contains,
+                        Box::new(Filter::Literal(Value::from("x"))),
+                    ),
+                ]),
+            ]),
+            Filter::Builtin(Builtin::Not),
+        ]);
+        let filter = parse_filter(input).expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn operator_name_filter() {
+        // operators are only keywords on their own
+        let input = ".and, .order, android";
+        assert!(parse_filter(input).is_err());
+        let filter = parse_filter(".and // .order").expect("parse error");
+        let expected = Filter::Alternative(vec![
+            Filter::Path(vec![Field::new("and").into()]),
+            Filter::Path(vec![Field::new("order").into()]),
+        ]);
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn nested_predicate_filter() {
+        let input = ".resource.*.select(.count > 1 and (.tags | has(\"env\")))";
+        let filter = parse_filter(input).expect("parse error");
+        let Filter::Path(segments) = filter else {
+            panic!("{filter:?} is not a path");
+        };
+        assert!(matches!(
+            &segments[2],
+            Segment::Select(Predicate {
+                filter: Filter::And(_)
+            })
+        ));
+    }
+
+    #[test]
+    fn variable_filter() {
+        let variables = Variables::from([
+            (String::from("name"), Value::from("prod-*")),
+            (String::from("key"), Value::from("with space")),
+            (String::from("min"), Value::from(2)),
+            (String::from("config"), Value::from_iter([("region", "eu")])),
+        ]);
+        let input = ".module{$name}.[$key].select(. > $min) | $config.region, $config.missing";
+        let filter = parse_filter_with_variables(input, &variables).expect("parse error");
+        // the values are matched exactly, and not as globs
+        let expected =
+            parse_filter(r#".module{"prod-\*"}.["with space"].select(. > 2) | "eu", null"#)
+                .expect("parse error");
+        assert_eq!(expected, filter);
+    }
+
+    #[test]
+    fn env_variable_filter() {
+        let filter = parse_filter("$ENV.PATH").expect("parse error");
+        let path = std::env::var("PATH").map_or(Value::Null, Value::from);
+        assert_eq!(Filter::Literal(path), filter);
+    }
+
+    #[test]
+    fn invalid_variable_filter() {
+        let variables = Variables::from([(String::from("count"), Value::from(1))]);
+        for input in [".module{$missing}", ".module{$count}", "$count.x"] {
+            assert!(parse_filter_with_variables(input, &variables).is_err());
+        }
+    }
+
+    #[test]
+    fn error_expected_tokens() {
+        let err = parse_filter("(.a").expect_err("unclosed group");
+        assert_eq!("expected an operator, `:`, `?`, `.` or `[`", err.label());
+        assert_eq!(3..3, err.span());
+        let err = parse_filter(".a | ").expect_err("missing filter");
+        assert_eq!("expected a filter", err.label());
+        let err = parse_filter(".a{~\"(\"}").expect_err("invalid regex");
+        assert_eq!("invalid regex: unclosed group", err.label());
+    }
+
+    #[test]
+    fn error_help() {
+        let help = |input| {
+            parse_filter(input)
+                .expect_err("parse error")
+                .help()
+                .to_vec()
+        };
+        assert_eq!(
+            ["paths start with a `.`, e.g. `.variable`"],
+            help("variable.default")[..]
+        );
+        assert_eq!(
+            ["labels are quoted, e.g. `{\"aws_instance\"}`"],
+            help(".resource{aws_instance}")[..]
+        );
+        assert_eq!(
+            ["`=` only sets a value with `hq write`, values are compared with `==`"],
+            help(".version = 1")[..]
+        );
+        assert!(help(".a.b c").is_empty());
+    }
+
+    #[test]
+    fn plain_error() {
+        let err = parse_filter(".a |\n  .b{x}").expect_err("unquoted label");
+        let expected = r#"error: failed to parse filter
+  |
+2 |   .b{x}
+  |      ^ expected `*`, `...`, a quoted label, a regex or a variable
+  |
+  = help: labels are quoted, e.g. `{"x"}`"#;
+        assert_eq!(expected, err.to_string());
+    }
+
+    #[test]
+    fn filter_paths() {
+        let filter = parse_filter(".a | .b, .c").expect("parse error");
+        let expected: Vec<Vec<Segment>> = vec![
+            vec![Field::new("a").into(), Field::new("b").into()],
+            vec![Field::new("a").into(), Field::new("c").into()],
+        ];
+        assert_eq!(Some(expected), filter.paths());
+
+        let filter = parse_filter(".a | keys").expect("parse error");
+        assert_eq!(None, filter.paths());
+    }
+
+    #[test]
+    fn resolve_negative_index() {
+        assert_eq!(Some(2), resolve_index(-1, 3));
+        assert_eq!(None, resolve_index(-4, 3));
+        assert_eq!(None, resolve_index(3, 3));
+    }
+
+    #[test]
+    fn resolve_clamped_slice() {
+        assert_eq!(1..3, resolve_slice(Some(1), Some(10), 3));
+        assert_eq!(1..3, resolve_slice(Some(-2), None, 3));
+        assert_eq!(2..2, resolve_slice(Some(2), Some(1), 3));
     }
 }
@@ -0,0 +1,86 @@
+//! glob and regex patterns that names and labels of a filter are matched
+//! against
+
+use regex::{Regex, RegexBuilder};
+
+/// a pattern that matches names or labels, e.g. `"prod-*"` or `~"^app-[0-9]+$"`
+///
+/// patterns with the case-insensitive flag end in `i`, e.g. `"prod-*"i`
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    /// the glob or regex as written in the filter
+    pub source: String,
+    /// whether `source` is a regex (rather than a glob)
+    pub is_regex: bool,
+    /// whether letters match regardless of their case
+    pub case_insensitive: bool,
+    regex: Regex,
+}
+
+impl Pattern {
+    /// a glob where `*` matches any characters and `?` matches a single one
+    /// (and `\` escapes the character after it, e.g. `\*` matches `*`)
+    pub fn glob(source: &str, case_insensitive: bool) -> Self {
+        let mut regex = String::from("^");
+        let mut chars = source.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                '\\' => {
+                    if let Some(c) = chars.next() {
+                        regex.push_str(&regex::escape(&c.to_string()));
+                    }
+                }
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        Pattern {
+            source: source.to_owned(),
+            is_regex: false,
+            case_insensitive,
+            // every special character was escaped, so this is a valid regex
+            regex: build(&regex, case_insensitive).unwrap(),
+        }
+    }
+
+    /// a regex, which matches anywhere in a name unless it is anchored with
+    /// `^` and `$`
+    pub fn regex(source: &str, case_insensitive: bool) -> Result<Self, regex::Error> {
+        Ok(Pattern {
+            source: source.to_owned(),
+            is_regex: true,
+            case_insensitive,
+            regex: build(source, case_insensitive)?,
+        })
+    }
+
+    /// whether `glob` contains any of the glob wildcards `*` or `?`
+    pub fn is_glob(glob: &str) -> bool {
+        glob.contains(['*', '?'])
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        self.regex.is_match(s)
+    }
+
+    /// the regex that the glob or regex is matched with
+    pub(crate) fn as_regex(&self) -> &Regex {
+        &self.regex
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+            && self.is_regex == other.is_regex
+            && self.case_insensitive == other.case_insensitive
+    }
+}
+
+fn build(regex: &str, case_insensitive: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(regex)
+        .case_insensitive(case_insensitive)
+        .build()
+}
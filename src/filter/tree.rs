@@ -0,0 +1,144 @@
+//! format a parsed [`Filter`] as an indented tree of what it is made of,
+//! which is how `hq explain` shows the way a filter was parsed
+
+use std::fmt::{Display, Write};
+
+use super::parser::{Filter, Segment, TemplatePart};
+
+impl Filter {
+    /// a line for each filter, operator and segment, with what is inside of
+    /// it indented below it, e.g. for `.a | length`
+    ///
+    /// ```text
+    /// pipe |
+    ///   path
+    ///     field .a
+    ///   builtin length
+    /// ```
+    pub fn tree(&self) -> String {
+        let mut tree = String::new();
+        write_filter(&mut tree, self, 0);
+        tree
+    }
+}
+
+fn write_filter(tree: &mut String, filter: &Filter, depth: usize) {
+    match filter {
+        Filter::Path(segments) if segments.is_empty() => write_line(tree, depth, "identity ."),
+        Filter::Path(segments) => {
+            write_line(tree, depth, "path");
+            for segment in segments {
+                write_segment(tree, segment, depth + 1);
+            }
+        }
+        Filter::Pipe(filters) => write_operands(tree, "pipe |", filters, depth),
+        Filter::Comma(filters) => write_operands(tree, "comma ,", filters, depth),
+        Filter::Alternative(filters) => write_operands(tree, "alternative //", filters, depth),
+        Filter::And(filters) => write_operands(tree, "and", filters, depth),
+        Filter::Or(filters) => write_operands(tree, "or", filters, depth),
+        Filter::Builtin(builtin) => write_line(tree, depth, format_args!("builtin {builtin}")),
+        Filter::Literal(_) => write_line(tree, depth, format_args!("literal {filter}")),
+        Filter::Array(filter) => {
+            write_line(tree, depth, "array");
+            write_filter(tree, filter, depth + 1);
+        }
+        Filter::Object(entries) => {
+            write_line(tree, depth, "object");
+            for (key, value) in entries {
+                write_line(tree, depth + 1, format_args!("key {key:?}"));
+                write_filter(tree, value, depth + 2);
+            }
+        }
+        Filter::Template(parts) => {
+            write_line(tree, depth, "template");
+            for part in parts {
+                match part {
+                    TemplatePart::Text(text) => {
+                        write_line(tree, depth + 1, format_args!("text {text:?}"))
+                    }
+                    TemplatePart::Interpolation(filter) => write_filter(tree, filter, depth + 1),
+                }
+            }
+        }
+        Filter::Compare(left, comparison, right) => {
+            write_line(tree, depth, format_args!("compare {comparison}"));
+            write_filter(tree, left, depth + 1);
+            write_filter(tree, right, depth + 1);
+        }
+        Filter::Arithmetic(left, operator, right) => {
+            write_line(tree, depth, format_args!("arithmetic {operator}"));
+            write_filter(tree, left, depth + 1);
+            write_filter(tree, right, depth + 1);
+        }
+    }
+}
+
+fn write_operands(tree: &mut String, operator: &str, operands: &[Filter], depth: usize) {
+    write_line(tree, depth, operator);
+    for operand in operands {
+        write_filter(tree, operand, depth + 1);
+    }
+}
+
+/// a segment is a single line (in its canonical form), except for the
+/// predicate of a `select(...)`
+fn write_segment(tree: &mut String, segment: &Segment, depth: usize) {
+    let kind = match segment {
+        Segment::Field(_) => "field",
+        Segment::Index(_) => "index",
+        Segment::Slice(..) => "slice",
+        Segment::Wildcard => "wildcard",
+        Segment::Iterate => "iterate",
+        Segment::Recursive(_) => "recursive",
+        Segment::Select(predicate) => {
+            write_line(tree, depth, "select");
+            return write_filter(tree, &predicate.filter, depth + 1);
+        }
+        Segment::Kind(_) => "kind",
+        Segment::Optional => "optional",
+    };
+    write_line(tree, depth, format_args!("{kind} {segment}"));
+}
+
+fn write_line(tree: &mut String, depth: usize, text: impl Display) {
+    // writing to a string cannot fail
+    let _ = writeln!(tree, "{:indent$}{text}", "", indent = depth * 2);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_filter;
+
+    #[test]
+    fn filter_tree() {
+        let filter = parse_filter(
+            r#".resource{"aws_instance", ...}.*.select(.count > 1)[0]? | {name: "\(labels)"}, length"#,
+        )
+        .expect("parse error");
+        let expected = r#"pipe |
+  path
+    field .resource{"aws_instance", ...}
+    wildcard .*
+    select
+      compare >
+        path
+          field .count
+        literal 1
+    index [0]
+    optional ?
+  comma ,
+    object
+      key "name"
+        template
+          builtin labels
+    builtin length
+"#;
+        assert_eq!(expected, filter.tree());
+    }
+
+    #[test]
+    fn identity_tree() {
+        let filter = parse_filter(".").expect("parse error");
+        assert_eq!("identity .\n", filter.tree());
+    }
+}
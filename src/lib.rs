@@ -2,7 +2,7 @@
 
 pub mod filter;
 pub use filter::parser;
-pub use filter::parser::parse_filter;
+pub use filter::parser::{parse_filter, Filter};
 
 pub mod query;
 pub use query::query;
@@ -12,3 +12,8 @@ pub use write::write;
 
 pub mod delete;
 pub use delete::delete;
+
+pub mod compiled;
+pub use compiled::CompiledFilter;
+
+mod parts;
@@ -0,0 +1,455 @@
+//! the parts of expressions other than arrays and objects that a filter can
+//! navigate into:
+//!
+//! * the arguments of a function call, by index like the elements of an
+//!   array (e.g. `.tags[1]` of `tags = merge(local.tags, { Name = "x" })`)
+//! * the interpolations of a template, by index (e.g. `.name[0]` of
+//!   `name = "${var.prefix}-logs"`), including the ones inside `%{if}` and
+//!   `%{for}` directives
+//! * the `condition`, `true` and `false` parts of a conditional
+//! * the `collection`, `key`, `value` and `condition` parts of a
+//!   for-expression (`key` and `condition` only when it has them)
+//!
+//! parentheses are skipped over (see [`unparenthesized`])
+//!
+//! it also has the helpers for [`hcl_edit`] expressions that both editing and
+//! tracing use, and the [`Cursor`] that both editors move through a path with
+
+use std::{error::Error, ops::Range};
+
+use hcl_edit::{
+    expr::{Array, Expression, ObjectKey},
+    structure::{Attribute, Block, Body},
+    template::{Directive, Element, Template},
+};
+
+use crate::{
+    parser::{following_qualifiers, resolve_index, Field, Filter, Segment},
+    query::{qualifier_matches, QueryResult},
+};
+
+/// how a part of an expression is selected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Part {
+    /// the n-th argument or interpolation
+    Index(usize),
+    /// e.g. `true` of a conditional
+    Name(&'static str),
+}
+
+impl Part {
+    /// whether `segment` selects this part, which is one of `count` parts
+    pub(crate) fn matches(&self, segment: &Segment, count: usize) -> bool {
+        match (self, segment) {
+            (Part::Index(index), Segment::Index(i)) => resolve_index(*i, count) == Some(*index),
+            (Part::Index(_), segment) => matches!(segment, Segment::Iterate),
+            // named parts are selected like the keys of an object
+            (Part::Name(name), segment) => segment.matches_object_key(Some(name)),
+        }
+    }
+
+    /// the segment that selects exactly this part
+    pub(crate) fn segment(&self) -> Segment {
+        match self {
+            Part::Index(index) => Segment::Index(*index as isize),
+            Part::Name(name) => Field::new(name).into(),
+        }
+    }
+}
+
+/// the parts of `expr`, which are empty for an expression that has none
+/// (e.g. a variable) or that is navigated in another way (an array or object)
+pub(crate) fn parts(expr: &hcl::Expression) -> Vec<(Part, hcl::Expression)> {
+    match expr {
+        hcl::Expression::FuncCall(call) => indexed(call.args.iter().cloned()),
+        hcl::Expression::TemplateExpr(template) => {
+            let mut interpolations = Vec::new();
+            // a template that does not parse has nothing to navigate into
+            if let Ok(template) = hcl::Template::from_expr(template) {
+                template_interpolations(&template, &mut interpolations);
+            }
+            indexed(interpolations)
+        }
+        hcl::Expression::Conditional(cond) => vec![
+            (Part::Name("condition"), cond.cond_expr.clone()),
+            (Part::Name("true"), cond.true_expr.clone()),
+            (Part::Name("false"), cond.false_expr.clone()),
+        ],
+        hcl::Expression::ForExpr(for_expr) => {
+            let mut parts = vec![(Part::Name("collection"), for_expr.collection_expr.clone())];
+            if let Some(key_expr) = &for_expr.key_expr {
+                parts.push((Part::Name("key"), key_expr.clone()));
+            }
+            parts.push((Part::Name("value"), for_expr.value_expr.clone()));
+            if let Some(cond_expr) = &for_expr.cond_expr {
+                parts.push((Part::Name("condition"), cond_expr.clone()));
+            }
+            parts
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn indexed<T>(exprs: impl IntoIterator<Item = T>) -> Vec<(Part, T)> {
+    exprs
+        .into_iter()
+        .enumerate()
+        .map(|(index, expr)| (Part::Index(index), expr))
+        .collect()
+}
+
+fn template_interpolations(template: &hcl::Template, interpolations: &mut Vec<hcl::Expression>) {
+    for element in template.elements() {
+        match element {
+            hcl::template::Element::Literal(_) => {}
+            hcl::template::Element::Interpolation(interpolation) => {
+                interpolations.push(interpolation.expr.clone());
+            }
+            hcl::template::Element::Directive(hcl::template::Directive::If(directive)) => {
+                template_interpolations(&directive.true_template, interpolations);
+                if let Some(false_template) = &directive.false_template {
+                    template_interpolations(false_template, interpolations);
+                }
+            }
+            hcl::template::Element::Directive(hcl::template::Directive::For(directive)) => {
+                template_interpolations(&directive.template, interpolations);
+            }
+        }
+    }
+}
+
+/// the parts of an [`hcl_edit`] expression (see [`parts`])
+pub(crate) fn edit_parts(expr: &Expression) -> Vec<(Part, &Expression)> {
+    match expr {
+        Expression::FuncCall(call) => indexed(call.args.iter()),
+        Expression::StringTemplate(template) => {
+            let mut interpolations = Vec::new();
+            edit_template_interpolations(template, &mut interpolations);
+            indexed(interpolations)
+        }
+        Expression::HeredocTemplate(heredoc) => {
+            let mut interpolations = Vec::new();
+            edit_template_interpolations(&heredoc.template, &mut interpolations);
+            indexed(interpolations)
+        }
+        Expression::Conditional(cond) => vec![
+            (Part::Name("condition"), &cond.cond_expr),
+            (Part::Name("true"), &cond.true_expr),
+            (Part::Name("false"), &cond.false_expr),
+        ],
+        Expression::ForExpr(for_expr) => {
+            let mut parts = vec![(Part::Name("collection"), &for_expr.intro.collection_expr)];
+            if let Some(key_expr) = &for_expr.key_expr {
+                parts.push((Part::Name("key"), key_expr));
+            }
+            parts.push((Part::Name("value"), &for_expr.value_expr));
+            if let Some(cond) = &for_expr.cond {
+                parts.push((Part::Name("condition"), &cond.expr));
+            }
+            parts
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn edit_template_interpolations<'a>(
+    template: &'a Template,
+    interpolations: &mut Vec<&'a Expression>,
+) {
+    for element in template.iter() {
+        match element {
+            Element::Literal(_) => {}
+            Element::Interpolation(interpolation) => interpolations.push(&interpolation.expr),
+            Element::Directive(Directive::If(directive)) => {
+                edit_template_interpolations(&directive.if_expr.template, interpolations);
+                if let Some(else_expr) = &directive.else_expr {
+                    edit_template_interpolations(&else_expr.template, interpolations);
+                }
+            }
+            Element::Directive(Directive::For(directive)) => {
+                edit_template_interpolations(&directive.for_expr.template, interpolations);
+            }
+        }
+    }
+}
+
+/// the parts of an [`hcl_edit`] expression that can be modified (see
+/// [`parts`])
+pub(crate) fn edit_parts_mut(expr: &mut Expression) -> Vec<(Part, &mut Expression)> {
+    match expr {
+        Expression::FuncCall(call) => indexed(call.args.iter_mut()),
+        Expression::StringTemplate(template) => {
+            let mut interpolations = Vec::new();
+            edit_template_interpolations_mut(template, &mut interpolations);
+            indexed(interpolations)
+        }
+        Expression::HeredocTemplate(heredoc) => {
+            let mut interpolations = Vec::new();
+            edit_template_interpolations_mut(&mut heredoc.template, &mut interpolations);
+            indexed(interpolations)
+        }
+        Expression::Conditional(cond) => vec![
+            (Part::Name("condition"), &mut cond.cond_expr),
+            (Part::Name("true"), &mut cond.true_expr),
+            (Part::Name("false"), &mut cond.false_expr),
+        ],
+        Expression::ForExpr(for_expr) => {
+            let mut parts = vec![(
+                Part::Name("collection"),
+                &mut for_expr.intro.collection_expr,
+            )];
+            if let Some(key_expr) = &mut for_expr.key_expr {
+                parts.push((Part::Name("key"), key_expr));
+            }
+            parts.push((Part::Name("value"), &mut for_expr.value_expr));
+            if let Some(cond) = &mut for_expr.cond {
+                parts.push((Part::Name("condition"), &mut cond.expr));
+            }
+            parts
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn edit_template_interpolations_mut<'a>(
+    template: &'a mut Template,
+    interpolations: &mut Vec<&'a mut Expression>,
+) {
+    for element in template.iter_mut() {
+        match element {
+            Element::Literal(_) => {}
+            Element::Interpolation(interpolation) => interpolations.push(&mut interpolation.expr),
+            Element::Directive(Directive::If(directive)) => {
+                edit_template_interpolations_mut(&mut directive.if_expr.template, interpolations);
+                if let Some(else_expr) = &mut directive.else_expr {
+                    edit_template_interpolations_mut(&mut else_expr.template, interpolations);
+                }
+            }
+            Element::Directive(Directive::For(directive)) => {
+                edit_template_interpolations_mut(&mut directive.for_expr.template, interpolations);
+            }
+        }
+    }
+}
+
+/// `expr` without the parentheses around it, e.g. `{ a = 1 }` of `({ a = 1 })`
+pub(crate) fn unparenthesized(expr: &hcl::Expression) -> &hcl::Expression {
+    match expr {
+        hcl::Expression::Parenthesis(inner) => unparenthesized(inner),
+        expr => expr,
+    }
+}
+
+/// an [`hcl_edit`] expression as a [`QueryResult`] (to check it against a
+/// predicate)
+pub(crate) fn expr_result(expr: &Expression) -> QueryResult {
+    QueryResult::Expr(expr.clone().into())
+}
+
+/// a slice of an [`hcl_edit`] array as a [`QueryResult`] (to check it against
+/// a predicate)
+pub(crate) fn slice_result(array: &Array, range: Range<usize>) -> QueryResult {
+    let elements = array.iter().skip(range.start).take(range.len());
+    QueryResult::Expr(hcl::Expression::Array(
+        elements.map(|element| element.clone().into()).collect(),
+    ))
+}
+
+/// the name of an object key that is an identifier or a string
+pub(crate) fn object_key_name(key: &ObjectKey) -> Option<&str> {
+    match key {
+        // some objects are keyed with an Ident
+        ObjectKey::Ident(id) => Some(id.as_str()),
+        // some objects are keyed with a String Expression
+        ObjectKey::Expression(expr) => expr.as_str(),
+    }
+}
+
+/// where an editor (see [`write`][crate::write] and [`delete`][crate::delete])
+/// is in the segments of a path, which moves to the next segment to visit
+/// what the current one matched and back again afterwards
+pub(crate) struct Cursor {
+    segments: Vec<Segment>,
+    /// whether each of the segments was marked as optional (e.g. `[5]?`)
+    optional: Vec<bool>,
+    index: usize,
+    current: Option<Segment>,
+    error: Option<Box<dyn Error>>,
+}
+
+impl Cursor {
+    /// a cursor at the first segment of a path (see
+    /// [`without_optional`][crate::parser::without_optional])
+    pub(crate) fn new((segments, optional): (Vec<Segment>, Vec<bool>)) -> Self {
+        // qualifiers at the start of the filter apply to the whole document,
+        // so they are checked before visiting it
+        let index = segments
+            .iter()
+            .take_while(|segment| segment.is_qualifier())
+            .count();
+        let current = segments.get(index).cloned();
+        Cursor {
+            segments,
+            optional,
+            index,
+            current,
+            error: None,
+        }
+    }
+
+    /// the segment that is matched against what is being visited, which is
+    /// `None` past the end of the path
+    pub(crate) fn current(&self) -> Option<Segment> {
+        self.current.clone()
+    }
+
+    pub(crate) fn next_segment(&mut self) {
+        // skip over the qualifiers that were applied to the current segment
+        self.index += 1 + self.qualifiers().count();
+        self.current = self.segments.get(self.index).cloned();
+    }
+
+    pub(crate) fn previous_segment(&mut self) {
+        self.index -= 1;
+        while self.segments[self.index].is_qualifier() {
+            self.index -= 1;
+        }
+        self.current = self.segments.get(self.index).cloned();
+    }
+
+    /// how many of the `.*`s that follow the current segment step over the
+    /// labels of a block it matched without naming them (see
+    /// [`Field::unnamed_labels`]), or `None` when the block does not satisfy
+    /// the qualifiers of one of those `.*`s
+    fn label_steps(&self, unnamed_labels: usize, block: impl Fn() -> QueryResult) -> Option<usize> {
+        let mut index = self.index;
+        let mut steps = 0;
+        while steps < unnamed_labels {
+            let next = index + 1 + following_qualifiers(&self.segments, index).count();
+            if !matches!(self.segments.get(next), Some(Segment::Wildcard)) {
+                break;
+            }
+            let mut qualifiers = following_qualifiers(&self.segments, next).peekable();
+            if qualifiers.peek().is_some() {
+                let block = block();
+                if !qualifiers.all(|qualifier| qualifier_matches(qualifier, &block)) {
+                    return None;
+                }
+            }
+            index = next;
+            steps += 1;
+        }
+        Some(steps)
+    }
+
+    /// whether the current segment (and its qualifiers) selects `attr`
+    pub(crate) fn selects_attr(&self, attr: &Attribute) -> bool {
+        self.current
+            .as_ref()
+            .is_some_and(|current| current.matches_attr(attr.key.as_str()))
+            && self.selects(|| expr_result(&attr.value))
+    }
+
+    /// whether the current segment (and its qualifiers) selects `block`, with
+    /// how many of the `.*`s after it step over the labels of the block
+    pub(crate) fn selects_block(&self, block: &Block) -> Option<usize> {
+        let current = self.current.as_ref()?;
+        let labels = block.labels.iter().map(|label| label.as_str());
+        let result = || QueryResult::Block(block.clone().into());
+        if !current.matches_block(block.ident.as_str(), labels) || !self.selects(result) {
+            return None;
+        }
+        let unnamed_labels = match current {
+            Segment::Field(field) => field.unnamed_labels(block.labels.len()),
+            _ => 0,
+        };
+        self.label_steps(unnamed_labels, result)
+    }
+
+    /// move past `steps` segments (see [`Cursor::selects_block`])
+    pub(crate) fn skip(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.next_segment();
+        }
+    }
+
+    /// move back over `steps` segments that were skipped
+    pub(crate) fn unskip(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.previous_segment();
+        }
+    }
+
+    /// whether the current segment is the last one, so that what it matches
+    /// is edited rather than visited
+    pub(crate) fn is_last(&self) -> bool {
+        self.index + self.qualifiers().count() >= self.segments.len() - 1
+    }
+
+    /// the predicates and kinds that narrow down what the current segment
+    /// matches
+    pub(crate) fn qualifiers(&self) -> impl Iterator<Item = &Segment> {
+        following_qualifiers(&self.segments, self.index)
+    }
+
+    /// the current segment with its qualifiers, e.g. `.lifecycle:block`
+    pub(crate) fn qualified(&self) -> Filter {
+        let end = self.index + self.qualifiers().count();
+        Filter::Path(self.segments[self.index..=end].to_vec())
+    }
+
+    /// whether the document satisfies the qualifiers at the start of the filter
+    pub(crate) fn selects_document(&self, body: &Body) -> bool {
+        // most filters start with a segment, so there is nothing to check
+        // (and no need to convert the document)
+        if self.index == 0 {
+            return true;
+        }
+        let document = QueryResult::Body(body.clone().into());
+        self.segments[..self.index]
+            .iter()
+            .all(|segment| qualifier_matches(segment, &document))
+    }
+
+    /// whether a match of the current segment also satisfies its qualifiers
+    pub(crate) fn selects(&self, candidate: impl FnOnce() -> QueryResult) -> bool {
+        let mut qualifiers = self.qualifiers().peekable();
+        if qualifiers.peek().is_none() {
+            return true;
+        }
+        let candidate = candidate();
+        qualifiers.all(|qualifier| qualifier_matches(qualifier, &candidate))
+    }
+
+    /// a field or index that matches nothing is an error (just like when
+    /// reading), unless it is optional
+    pub(crate) fn missing(&mut self) {
+        let can_be_missing = matches!(self.current, Some(Segment::Field(_) | Segment::Index(_)));
+        // a predicate can leave nothing of what the segment matched
+        let has_predicate = self
+            .qualifiers()
+            .any(|qualifier| matches!(qualifier, Segment::Select(_)));
+        if !can_be_missing || has_predicate || self.optional[self.index] {
+            return;
+        }
+        let qualified = self.qualified();
+        self.fail(format!(
+            "`{qualified}` does not exist (mark it as optional with `?`, e.g. `{qualified}?`)"
+        ));
+    }
+
+    /// keep the first error, since the editor carries on after it
+    pub(crate) fn fail(&mut self, message: String) {
+        if self.error.is_none() {
+            self.error = Some(message.into());
+        }
+    }
+
+    /// the first error of the edit, if there was one
+    pub(crate) fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
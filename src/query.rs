@@ -1,17 +1,47 @@
 //! use the [`hcl-rs`][hcl] crate to query HCL documents
 
-use std::error::Error;
+use std::{
+    cell::OnceCell,
+    cmp::Ordering,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    iter,
+    rc::Rc,
+};
 
-use hcl::{Block, Body, Expression, Identifier, ObjectKey};
+use hcl::{
+    format::Format, Block, Body, Expression, Identifier, Object, ObjectKey, Structure, Value,
+};
 
-use crate::parser::Field;
+use crate::{
+    parser::{
+        resolve_index, resolve_slice, Builtin, Comparison, Field, Filter, Kind, Predicate, Segment,
+        TemplatePart,
+    },
+    parts::{parts, unparenthesized},
+};
+
+mod arithmetic;
+mod builtin;
+mod collection;
+mod suggest;
+mod trace;
+use arithmetic::arithmetic;
+use builtin::{builtin_query, type_name};
+use collection::{collected, collection_query, is_collection};
+use suggest::{did_you_mean, suggestions};
+pub use trace::{locate, trace, Step, Traced};
 
 /// a portion of an HCL document that matched the provided filter
+#[derive(Clone, Debug)]
 pub enum QueryResult {
     /// an HCL [`Expression`] matched the filter
     Expr(Expression),
     /// an HCL [`Block`] matched the filter
     Block(Block),
+    /// an HCL [`Body`] matched the filter (i.e. the whole document matched
+    /// the identity filter `.`)
+    Body(Body),
 }
 
 impl QueryResult {
@@ -21,68 +51,593 @@ impl QueryResult {
             // https://github.com/martinohmann/hcl-rs/issues/344
             Self::Expr(expr) => hcl::format::to_string(expr)?,
             Self::Block(block) => hcl::format::to_string(block)?,
+            Self::Body(body) => hcl::format::to_string(body)?,
         };
         Ok(s)
     }
 }
 
-/// given a vector of [`Field`]s return a vector of [`QueryResult`]s
+/// a result while a filter is applied, together with the block, value or
+/// document that it is part of (which is what `parent` returns)
+#[derive(Debug)]
+pub(crate) struct Item<'a> {
+    result: OnceCell<QueryResult>,
+    /// the document, which is only copied into `result` when something
+    /// other than a segment (e.g. a predicate or `.` itself) needs it
+    document: Option<&'a Body>,
+    /// `None` for the document itself and for a result that is not part of
+    /// the document (e.g. computed by `length`)
+    parent: Option<Rc<Item<'a>>>,
+    /// the labels of a block that neither the field that matched it named
+    /// nor `.*` has stepped over yet (see [`Field::unnamed_labels`])
+    unnamed_labels: usize,
+}
+
+impl<'a> Item<'a> {
+    /// a result without a parent
+    pub(crate) fn new(result: QueryResult) -> Rc<Self> {
+        Rc::new(Item {
+            result: OnceCell::from(result),
+            document: None,
+            parent: None,
+            unnamed_labels: 0,
+        })
+    }
+
+    /// the whole document, which is where every filter starts
+    fn document(body: &'a Body) -> Rc<Self> {
+        Rc::new(Item {
+            result: OnceCell::new(),
+            document: Some(body),
+            parent: None,
+            unnamed_labels: 0,
+        })
+    }
+
+    /// a result that is part of `parent`
+    fn child(parent: &Rc<Item<'a>>, result: QueryResult) -> Rc<Self> {
+        Item::matched(parent, result, None)
+    }
+
+    /// a result that is part of `parent`, which `field` matched
+    fn matched(parent: &Rc<Item<'a>>, result: QueryResult, field: Option<&Field>) -> Rc<Self> {
+        let unnamed_labels = match (field, &result) {
+            (Some(field), QueryResult::Block(block)) => field.unnamed_labels(block.labels().len()),
+            _ => 0,
+        };
+        Rc::new(Item {
+            result: OnceCell::from(result),
+            document: None,
+            parent: Some(Rc::clone(parent)),
+            unnamed_labels,
+        })
+    }
+
+    /// the same block, after `.*` stepped over one of its unnamed labels
+    fn stepped(&self) -> Rc<Self> {
+        Rc::new(Item {
+            result: self.result.clone(),
+            document: None,
+            parent: self.parent.clone(),
+            unnamed_labels: self.unnamed_labels - 1,
+        })
+    }
+
+    /// what was matched, which copies the document the first time it is
+    /// needed
+    pub(crate) fn result(&self) -> &QueryResult {
+        self.result.get_or_init(|| {
+            // only the document is created without a result
+            QueryResult::Body(self.document.cloned().unwrap_or_default())
+        })
+    }
+
+    /// the body of the document or of a block result (without copying the
+    /// document), for segments that match what is inside of it
+    fn body(&self) -> Option<&Body> {
+        match self.document {
+            Some(body) => Some(body),
+            None => match self.result() {
+                QueryResult::Body(body) => Some(body),
+                QueryResult::Block(block) => Some(block.body()),
+                QueryResult::Expr(_) => None,
+            },
+        }
+    }
+
+    /// every result that `self` is part of, nearest first
+    fn ancestors(&self) -> impl Iterator<Item = Rc<Item<'a>>> {
+        iter::successors(self.parent.clone(), |item| item.parent.clone())
+    }
+}
+
+/// given a [`Filter`] return a vector of [`QueryResult`]s
 ///
 /// a result vector with multiple results indicates that multiple entities
 /// matched the provided filter
-pub fn query(fields: &mut Vec<Field>, body: &Body) -> Vec<QueryResult> {
-    if fields.is_empty() {
-        // our grammar/parser for filters won't allow an empty filter
-        unreachable!();
+///
+/// a field or index that matches nothing is an error, unless it is marked as
+/// optional with `?` (or it is on the left side of `//`)
+///
+/// neither `filter` nor `body` is modified, so a filter can be applied to any
+/// number of documents (see [`CompiledFilter`][crate::CompiledFilter])
+pub fn query(filter: &Filter, body: &Body) -> Result<Vec<QueryResult>, Box<dyn Error>> {
+    // start with the whole document (which is all the identity filter `.`
+    // will return)
+    let document = Item::document(body);
+    let items = filter_query(filter, vec![document], true)?;
+    Ok(items.iter().map(|item| item.result().clone()).collect())
+}
+
+/// a segment of a filter that matched nothing, although there was something
+/// for it to match (see [`diagnose`])
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    /// the path of the filter up to (and including) the segment
+    pub path: Vec<Segment>,
+    /// similar fields that would have matched something (closest first)
+    pub suggestions: Vec<Segment>,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // a mismatch always has a segment
+        let segment = self.path.last().unwrap();
+        if self.path.len() > 1 {
+            let path = Filter::Path(self.path.clone());
+            write!(f, "`{segment}` of `{path}` matched nothing")?;
+        } else {
+            write!(f, "`{segment}` matched nothing")?;
+        }
+        if let Some(did_you_mean) = did_you_mean(&self.suggestions) {
+            write!(f, ", {did_you_mean}")?;
+        }
+        Ok(())
+    }
+}
+
+/// find the segment of each path of `filter` that stopped matching, which
+/// explains why `filter` has no results
+///
+/// only a filter that combines paths with `|` and `,` can be diagnosed, and
+/// missing fields are not an error (just like in a predicate)
+pub fn diagnose(filter: &Filter, body: &Body) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    for path in filter.paths().unwrap_or_default() {
+        let mut items = vec![Item::document(body)];
+        for (index, segment) in path.iter().enumerate() {
+            let matches = result_query(segment, items.clone());
+            if matches.is_empty() {
+                mismatches.push(Mismatch {
+                    path: path[..=index].to_vec(),
+                    suggestions: suggestions(segment, &results(&items)),
+                });
+                break;
+            }
+            items = matches;
+        }
+    }
+    mismatches
+}
+
+/// every attribute, block, object value and array element of `body` (at any
+/// depth) in document order, with the path that matches exactly it
+///
+/// a path formats (with [`Filter::Path`]) as a filter that can be passed to
+/// [`query`], [`write`][crate::write()] or [`delete`][crate::delete()], e.g.
+/// `.resource{"aws_s3_bucket", "logs"}.tags.Name`
+///
+/// the value of a computed object key (e.g. `(var.name) = 1`) has no such
+/// path, so it is left out (along with everything inside of it)
+pub fn paths(body: &Body) -> Vec<(Vec<Segment>, QueryResult)> {
+    let mut paths = Vec::new();
+    collect_paths(&Item::document(body), &mut Vec::new(), &mut paths);
+    paths
+}
+
+fn collect_paths<'a>(
+    item: &Rc<Item<'a>>,
+    path: &mut Vec<Segment>,
+    paths: &mut Vec<(Vec<Segment>, QueryResult)>,
+) {
+    // `[]` matches everything inside of an expression (but nothing of a body)
+    let segment = match item.body() {
+        Some(_) => Segment::Wildcard,
+        None => Segment::Iterate,
+    };
+    for (relative, item) in located_query(&segment, item) {
+        // `.*` is the location of a computed key (see `key_segment`), but as a
+        // path it would match every other key as well
+        if relative.contains(&Segment::Wildcard) {
+            continue;
+        }
+        let len = path.len();
+        path.extend(relative);
+        paths.push((path.clone(), item.result().clone()));
+        collect_paths(&item, path, paths);
+        path.truncate(len);
     }
+}
 
-    // take the first field and do a `Body` query
-    // e.g. `.foo.bar` will start with 'foo'
-    let field = fields.remove(0);
-    let mut query_result = body_query(&field, body);
+/// the results of `items`
+fn results<'a>(items: &[Rc<Item<'a>>]) -> Vec<QueryResult> {
+    items.iter().map(|item| item.result().clone()).collect()
+}
 
-    // iteratively evaluate each subsequent field
-    // e.g. having handled 'foo' we move on to 'bar'
-    while !fields.is_empty() {
-        let field = fields.remove(0);
-        query_result = result_query(&field, query_result);
+/// apply `filter` to each of `items`
+///
+/// unless `strict`, a field or index that matches nothing is not an error
+/// (which is how the filter of a predicate is applied)
+fn filter_query<'a>(
+    filter: &Filter,
+    items: Vec<Rc<Item<'a>>>,
+    strict: bool,
+) -> Result<Vec<Rc<Item<'a>>>, Box<dyn Error>> {
+    match filter {
+        Filter::Path(segments) => path_query(segments, items, strict),
+        // each filter's results are the input of the next filter
+        Filter::Pipe(filters) => filters
+            .iter()
+            .try_fold(items, |items, filter| filter_query(filter, items, strict)),
+        // every filter is applied to each input, one after another
+        Filter::Comma(filters) => {
+            let mut matches = Vec::new();
+            for item in items {
+                for filter in filters {
+                    let input = vec![item.clone()];
+                    matches.append(&mut filter_query(filter, input, strict)?);
+                }
+            }
+            Ok(matches)
+        }
+        // what encloses each input (nothing for the document itself)
+        Filter::Builtin(Builtin::Parent) => Ok(items
+            .iter()
+            .filter_map(|item| item.parent.clone())
+            .collect()),
+        Filter::Builtin(Builtin::Ancestors) => {
+            Ok(items.iter().flat_map(|item| item.ancestors()).collect())
+        }
+        // these summarise each input, which is a list (or a body)
+        Filter::Builtin(builtin) if is_collection(builtin) => {
+            collection_query(builtin, items, strict)
+        }
+        // functions are applied to each input
+        Filter::Builtin(builtin) => {
+            let mut results = Vec::new();
+            for item in &items {
+                results.extend(builtin_query(builtin, item.result())?.map(Item::new));
+            }
+            Ok(results)
+        }
+        // every filter is applied to each input until one of them has a
+        // result other than `false` or `null`
+        Filter::Alternative(filters) => {
+            let mut matches = Vec::new();
+            for item in items {
+                for (index, filter) in filters.iter().enumerate() {
+                    let input = vec![item.clone()];
+                    if index == filters.len() - 1 {
+                        matches.append(&mut filter_query(filter, input, strict)?);
+                        break;
+                    }
+                    // a filter that fails is the same as one without results
+                    let mut results: Vec<_> = filter_query(filter, input, strict)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|item| is_truthy(item.result()))
+                        .collect();
+                    if !results.is_empty() {
+                        matches.append(&mut results);
+                        break;
+                    }
+                }
+            }
+            Ok(matches)
+        }
+        // a literal is the result for each input
+        Filter::Literal(value) => Ok(items
+            .iter()
+            .map(|_| Item::new(QueryResult::Expr(Expression::from(value.clone()))))
+            .collect()),
+        Filter::Template(parts) => template_query(parts, items, strict),
+        // all of the results of the filter (for each input) in one list, or
+        // in one body when they are blocks
+        Filter::Array(filter) => items
+            .into_iter()
+            .map(|item| collected(&filter_query(filter, vec![item], strict)?))
+            .collect(),
+        Filter::Object(entries) => construct_object(entries, items, strict),
+        Filter::Compare(left, comparison, right) => {
+            binary_query(left, right, items, strict, |left, right| {
+                Ok(Expression::Bool(compare(left, *comparison, right)))
+            })
+        }
+        Filter::Arithmetic(left, operator, right) => {
+            binary_query(left, right, items, strict, |left, right| {
+                arithmetic(*operator, left, right)
+            })
+        }
+        Filter::And(filters) => logical_query(filters, items, strict, false),
+        Filter::Or(filters) => logical_query(filters, items, strict, true),
     }
+}
 
-    query_result
+/// combine each result of `left` with each result of `right` (both applied
+/// to the same input), the same way `jq` does
+fn binary_query<'a, F>(
+    left: &Filter,
+    right: &Filter,
+    items: Vec<Rc<Item<'a>>>,
+    strict: bool,
+    operator: F,
+) -> Result<Vec<Rc<Item<'a>>>, Box<dyn Error>>
+where
+    F: Fn(&QueryResult, &QueryResult) -> Result<Expression, Box<dyn Error>>,
+{
+    let mut matches = Vec::new();
+    for item in items {
+        let lefts = filter_query(left, vec![item.clone()], strict)?;
+        let rights = filter_query(right, vec![item], strict)?;
+        for right in &rights {
+            for left in &lefts {
+                let result = operator(left.result(), right.result())?;
+                matches.push(Item::new(QueryResult::Expr(result)));
+            }
+        }
+    }
+    Ok(matches)
 }
 
-fn body_query(field: &Field, body: &Body) -> Vec<QueryResult> {
+/// an object for each combination of the results of the filters of its keys
+/// (each applied to the same input), the same way `jq` does
+fn construct_object<'a>(
+    entries: &[(String, Filter)],
+    items: Vec<Rc<Item<'a>>>,
+    strict: bool,
+) -> Result<Vec<Rc<Item<'a>>>, Box<dyn Error>> {
     let mut matches = Vec::new();
-    let mut attr_matches = attr_query(&field.name, body);
-    matches.append(&mut attr_matches);
-    let mut block_matches = block_query(field, body);
-    matches.append(&mut block_matches);
-    matches
+    for item in items {
+        let mut objects = vec![Object::new()];
+        for (key, filter) in entries {
+            let mut next_objects = Vec::new();
+            for object in &objects {
+                for value in filter_query(filter, vec![Rc::clone(&item)], strict)? {
+                    let QueryResult::Expr(value) = value.result() else {
+                        let type_name = type_name(value.result());
+                        return Err(format!("the value of `{key}` cannot be a {type_name}").into());
+                    };
+                    let mut object = object.clone();
+                    object.insert(object_key(key), value.clone());
+                    next_objects.push(object);
+                }
+            }
+            objects = next_objects;
+        }
+        matches.extend(
+            objects
+                .into_iter()
+                .map(|object| Item::new(QueryResult::Expr(Expression::Object(object)))),
+        );
+    }
+    Ok(matches)
 }
 
-fn attr_query(field: &str, body: &Body) -> Vec<QueryResult> {
+/// a string for each combination of the results of the interpolated filters
+/// (each applied to the same input), the same way `jq` does
+fn template_query<'a>(
+    parts: &[TemplatePart],
+    items: Vec<Rc<Item<'a>>>,
+    strict: bool,
+) -> Result<Vec<Rc<Item<'a>>>, Box<dyn Error>> {
     let mut matches = Vec::new();
-    for attr in body.attributes() {
-        if attr.key() == field {
-            matches.push(QueryResult::Expr(attr.expr().clone()));
+    for item in items {
+        let mut strings = vec![String::new()];
+        for part in parts {
+            strings = match part {
+                TemplatePart::Text(text) => strings.into_iter().map(|s| s + text).collect(),
+                TemplatePart::Interpolation(filter) => {
+                    let mut next_strings = Vec::new();
+                    for result in filter_query(filter, vec![Rc::clone(&item)], strict)? {
+                        let text = interpolated(result.result())?;
+                        next_strings.extend(strings.iter().map(|s| format!("{s}{text}")));
+                    }
+                    next_strings
+                }
+            };
         }
+        matches.extend(
+            strings
+                .into_iter()
+                .map(|s| Item::new(QueryResult::Expr(Expression::String(s)))),
+        );
+    }
+    Ok(matches)
+}
+
+/// a string as it is, and any other value formatted as HCL on a single line
+fn interpolated(query_result: &QueryResult) -> Result<String, Box<dyn Error>> {
+    match query_result {
+        QueryResult::Expr(Expression::String(s)) => Ok(s.clone()),
+        QueryResult::Expr(expr) => {
+            let mut formatter = hcl::format::Formatter::builder().compact(true).build_vec();
+            Ok(expr.format_string(&mut formatter)?)
+        }
+        query_result => Err(format!(
+            "a {} cannot be interpolated into a string",
+            type_name(query_result)
+        )
+        .into()),
     }
-    matches
 }
 
-fn block_query(field: &Field, body: &Body) -> Vec<QueryResult> {
+/// `and` (when `short_circuit` is `false`) or `or` (when it is `true`)
+///
+/// the filters after a result that is `short_circuit` are not applied, and a
+/// filter without any results counts as `false` (e.g. `.public? or .shared`)
+fn logical_query<'a>(
+    filters: &[Filter],
+    items: Vec<Rc<Item<'a>>>,
+    strict: bool,
+    short_circuit: bool,
+) -> Result<Vec<Rc<Item<'a>>>, Box<dyn Error>> {
     let mut matches = Vec::new();
-    for block in body.blocks() {
-        if block.identifier() != field.name {
+    for item in items {
+        let mut results = vec![!short_circuit];
+        for filter in filters {
+            let mut next_results = Vec::new();
+            for result in results {
+                if result == short_circuit {
+                    next_results.push(result);
+                    continue;
+                }
+                let operands = filter_query(filter, vec![item.clone()], strict)?;
+                if operands.is_empty() {
+                    next_results.push(false);
+                }
+                next_results.extend(operands.iter().map(|item| is_truthy(item.result())));
+            }
+            results = next_results;
+        }
+        matches.extend(
+            results
+                .into_iter()
+                .map(|result| Item::new(QueryResult::Expr(Expression::Bool(result)))),
+        );
+    }
+    Ok(matches)
+}
+
+/// iteratively evaluate each segment
+/// e.g. `.foo.bar` will start with 'foo' and then move on to 'bar'
+fn path_query<'a>(
+    segments: &[Segment],
+    mut items: Vec<Rc<Item<'a>>>,
+    strict: bool,
+) -> Result<Vec<Rc<Item<'a>>>, Box<dyn Error>> {
+    let mut index = 0;
+    while index < segments.len() {
+        let segment = &segments[index];
+        // a kind is part of the segment before it, e.g. `.lifecycle:block` is
+        // missing when there is only an attribute `lifecycle`
+        let len = match segments.get(index + 1) {
+            Some(Segment::Kind(_)) => 2,
+            _ => 1,
+        };
+        let qualified = &segments[index..index + len];
+        index += len;
+        let optional = matches!(segments.get(index), Some(Segment::Optional));
+        // only a field or index can be missing, the other segments can
+        // legitimately match nothing (e.g. `[]` of an empty array)
+        let can_be_missing = matches!(segment, Segment::Field(_) | Segment::Index(_));
+        if !strict || !can_be_missing || optional || items.is_empty() {
+            items = qualified_query(qualified, items);
             continue;
         }
-        if field.labels.is_empty() {
-            matches.push(QueryResult::Block(block.clone()));
+        let inputs = items.clone();
+        items = qualified_query(qualified, items);
+        if items.is_empty() {
+            // the segment itself matches something when it is only missing
+            // because of its kind
+            let mut suggestions = suggestions(segment, &results(&inputs));
+            suggestions.retain(|suggestion| suggestion != segment);
+            let qualified = Filter::Path(qualified.to_vec());
+            return Err(match did_you_mean(&suggestions) {
+                Some(did_you_mean) => format!("`{qualified}` does not exist ({did_you_mean})"),
+                None => format!(
+                    "`{qualified}` does not exist (mark it as optional with `?`, e.g. `{qualified}?`)"
+                ),
+            }
+            .into());
         }
-        for filter_label in &field.labels {
-            for block_label in block.labels() {
-                if block_label.as_str() == filter_label {
-                    matches.push(QueryResult::Block(block.clone()));
+    }
+    Ok(items)
+}
+
+fn qualified_query<'a>(segments: &[Segment], items: Vec<Rc<Item<'a>>>) -> Vec<Rc<Item<'a>>> {
+    segments
+        .iter()
+        .fold(items, |items, segment| result_query(segment, items))
+}
+
+fn result_query<'a>(segment: &Segment, items: Vec<Rc<Item<'a>>>) -> Vec<Rc<Item<'a>>> {
+    items
+        .iter()
+        .flat_map(|item| located_query(segment, item))
+        .map(|(_, item)| item)
+        .collect()
+}
+
+/// a result of a segment and its path (of segments that match exactly it)
+/// relative to the input of the segment, e.g. `.variable{"my_var"}` for the
+/// segment `.variable`
+pub(crate) type Located<'a> = (Vec<Segment>, Rc<Item<'a>>);
+
+/// apply `segment` to `item`
+pub(crate) fn located_query<'a>(segment: &Segment, item: &Rc<Item<'a>>) -> Vec<Located<'a>> {
+    match segment {
+        Segment::Recursive(field) => {
+            let mut matches = Vec::new();
+            descendant_query(field, item, &mut Vec::new(), &mut matches);
+            matches
+        }
+        Segment::Select(_) | Segment::Kind(_) => {
+            if item_qualifier_matches(segment, item) {
+                vec![(Vec::new(), Rc::clone(item))]
+            } else {
+                Vec::new()
+            }
+        }
+        // the previous segment has already been evaluated
+        Segment::Optional => vec![(Vec::new(), Rc::clone(item))],
+        // a label that the field before did not name is stepped over before
+        // `.*` matches what is inside of the block (e.g. `.resource.*.*` is
+        // every resource, just like `resource.<type>.<name>` in JSON)
+        Segment::Wildcard if item.unnamed_labels > 0 => vec![(Vec::new(), item.stepped())],
+        _ => {
+            let field = match segment {
+                Segment::Field(field) => Some(field),
+                _ => None,
+            };
+            children_query(segment, item)
+                .into_iter()
+                .map(|(path, result)| (path, Item::matched(item, result, field)))
+                .collect()
+        }
+    }
+}
+
+/// the parts of `item` that `segment` matches
+fn children_query(segment: &Segment, item: &Item) -> Vec<(Vec<Segment>, QueryResult)> {
+    if let Some(body) = item.body() {
+        return body_query(segment, body);
+    }
+    match item.result() {
+        QueryResult::Expr(expr) => match unparenthesized(expr) {
+            Expression::Object(object) => object_query(segment, object),
+            Expression::Array(array) => array_query(segment, array),
+            // e.g. the arguments of a function call
+            expr => parts_query(segment, expr),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn body_query(segment: &Segment, body: &Body) -> Vec<(Vec<Segment>, QueryResult)> {
+    let mut matches = Vec::new();
+    for structure in body.iter() {
+        match structure {
+            Structure::Attribute(attr) => {
+                if segment.matches_attr(attr.key()) {
+                    let path = vec![Field::new(attr.key()).into()];
+                    matches.push((path, QueryResult::Expr(attr.expr().clone())));
+                }
+            }
+            Structure::Block(block) => {
+                let labels = block.labels().iter().map(|label| label.as_str());
+                if segment.matches_block(block.identifier(), labels) {
+                    let path = vec![block_field(block).into()];
+                    matches.push((path, QueryResult::Block(block.clone())));
                 }
             }
         }
@@ -90,31 +645,263 @@ fn block_query(field: &Field, body: &Body) -> Vec<QueryResult> {
     matches
 }
 
-fn result_query(field: &Field, query_results: Vec<QueryResult>) -> Vec<QueryResult> {
+fn object_query(
+    segment: &Segment,
+    object: &Object<ObjectKey, Expression>,
+) -> Vec<(Vec<Segment>, QueryResult)> {
     let mut matches = Vec::new();
-    for query_result in query_results {
-        match query_result {
-            QueryResult::Expr(expr) => {
-                if let Expression::Object(object) = expr {
-                    // some objects are keyed with an Identifier
-                    if let Ok(id) = Identifier::new(&field.name) {
-                        let key = ObjectKey::Identifier(id);
-                        if let Some(expr) = object.get(&key) {
-                            matches.push(QueryResult::Expr(expr.clone()));
-                        }
-                    }
-                    // some objects are keyed with a String Expression
-                    let key = ObjectKey::Expression(Expression::String(field.name.clone()));
-                    if let Some(expr) = object.get(&key) {
-                        matches.push(QueryResult::Expr(expr.clone()));
-                    }
-                }
+    for (key, expr) in object {
+        if segment.matches_object_key(object_key_name(key)) {
+            matches.push((vec![key_segment(key)], QueryResult::Expr(expr.clone())));
+        }
+    }
+    matches
+}
+
+fn array_query(segment: &Segment, array: &[Expression]) -> Vec<(Vec<Segment>, QueryResult)> {
+    let mut matches = Vec::new();
+    match segment {
+        Segment::Index(index) => {
+            if let Some(index) = resolve_index(*index, array.len()) {
+                let path = vec![Segment::Index(index as isize)];
+                matches.push((path, QueryResult::Expr(array[index].clone())));
             }
-            QueryResult::Block(block) => {
-                let mut body_matches = body_query(field, block.body());
-                matches.append(&mut body_matches);
+        }
+        Segment::Slice(start, end) => {
+            let range = resolve_slice(*start, *end, array.len());
+            let slice = Expression::Array(array[range].to_vec());
+            matches.push((vec![segment.clone()], QueryResult::Expr(slice)));
+        }
+        Segment::Iterate => {
+            for (index, element) in array.iter().enumerate() {
+                let path = vec![Segment::Index(index as isize)];
+                matches.push((path, QueryResult::Expr(element.clone())));
             }
         }
+        _ => {}
     }
     matches
 }
+
+fn parts_query(segment: &Segment, expr: &Expression) -> Vec<(Vec<Segment>, QueryResult)> {
+    let parts = parts(expr);
+    let count = parts.len();
+    parts
+        .into_iter()
+        .filter(|(part, _)| part.matches(segment, count))
+        .map(|(part, expr)| (vec![part.segment()], QueryResult::Expr(expr)))
+        .collect()
+}
+
+/// the field that matches exactly `block`
+fn block_field(block: &Block) -> Field {
+    let labels: Vec<_> = block.labels().iter().map(|label| label.as_str()).collect();
+    Field::labeled(block.identifier(), &labels)
+}
+
+/// the segment that matches exactly the value of `key`
+fn key_segment(key: &ObjectKey) -> Segment {
+    match object_key_name(key) {
+        Some(name) => Field::new(name).into(),
+        // a key that is computed (e.g. `(var.name)`) cannot be matched by
+        // name, only by `.*`
+        None => Segment::Wildcard,
+    }
+}
+
+/// collect every attribute, block and object value nested (at any depth)
+/// inside `item` that matches `field`, in document order
+///
+/// `path` is the path from `item` to the current position
+fn descendant_query<'a>(
+    field: &Field,
+    item: &Rc<Item<'a>>,
+    path: &mut Vec<Segment>,
+    matches: &mut Vec<Located<'a>>,
+) {
+    if let Some(body) = item.body() {
+        return body_descendant_query(field, body, item, path, matches);
+    }
+    if let QueryResult::Expr(expr) = item.result() {
+        expr_descendant_query(field, expr, item, path, matches);
+    }
+}
+
+fn body_descendant_query<'a>(
+    field: &Field,
+    body: &Body,
+    parent: &Rc<Item<'a>>,
+    path: &mut Vec<Segment>,
+    matches: &mut Vec<Located<'a>>,
+) {
+    for structure in body.iter() {
+        match structure {
+            Structure::Attribute(attr) => {
+                path.push(Field::new(attr.key()).into());
+                let item = Item::child(parent, QueryResult::Expr(attr.expr().clone()));
+                if field.matches_attr(attr.key()) {
+                    matches.push((path.clone(), Rc::clone(&item)));
+                }
+                descendant_query(field, &item, path, matches);
+                path.pop();
+            }
+            Structure::Block(block) => {
+                path.push(block_field(block).into());
+                let item = Item::matched(parent, QueryResult::Block(block.clone()), Some(field));
+                let labels = block.labels().iter().map(|label| label.as_str());
+                if field.matches_block(block.identifier(), labels) {
+                    matches.push((path.clone(), Rc::clone(&item)));
+                }
+                descendant_query(field, &item, path, matches);
+                path.pop();
+            }
+        }
+    }
+}
+
+fn expr_descendant_query<'a>(
+    field: &Field,
+    expr: &Expression,
+    parent: &Rc<Item<'a>>,
+    path: &mut Vec<Segment>,
+    matches: &mut Vec<Located<'a>>,
+) {
+    match unparenthesized(expr) {
+        Expression::Object(object) => {
+            for (key, value) in object {
+                path.push(key_segment(key));
+                let item = Item::child(parent, QueryResult::Expr(value.clone()));
+                if field.matches_object_key(object_key_name(key)) {
+                    matches.push((path.clone(), Rc::clone(&item)));
+                }
+                descendant_query(field, &item, path, matches);
+                path.pop();
+            }
+        }
+        Expression::Array(array) => {
+            for (index, element) in array.iter().enumerate() {
+                path.push(Segment::Index(index as isize));
+                let item = Item::child(parent, QueryResult::Expr(element.clone()));
+                descendant_query(field, &item, path, matches);
+                path.pop();
+            }
+        }
+        // the names of parts (e.g. `true` of a conditional) are not matched,
+        // only what is inside of them
+        expr => {
+            for (part, value) in parts(expr) {
+                path.push(part.segment());
+                let item = Item::child(parent, QueryResult::Expr(value));
+                descendant_query(field, &item, path, matches);
+                path.pop();
+            }
+        }
+    }
+}
+
+/// whether `item` satisfies `predicate`, i.e. whether its filter has any
+/// result other than `false` or `null`
+fn predicate_matches<'a>(predicate: &Predicate, item: &Rc<Item<'a>>) -> bool {
+    // a predicate that fails (e.g. adding a string to a number) does not match
+    filter_query(&predicate.filter, vec![Rc::clone(item)], false)
+        .is_ok_and(|items| items.iter().any(|item| is_truthy(item.result())))
+}
+
+/// whether `query_result` satisfies a qualifier (see
+/// [`Segment::is_qualifier`]), which any other segment always does
+///
+/// `query_result` has no parent, so a predicate that uses `parent` does not
+/// match it
+pub(crate) fn qualifier_matches(segment: &Segment, query_result: &QueryResult) -> bool {
+    item_qualifier_matches(segment, &Item::new(query_result.clone()))
+}
+
+fn item_qualifier_matches<'a>(segment: &Segment, item: &Rc<Item<'a>>) -> bool {
+    match (segment, item.result()) {
+        (Segment::Select(predicate), _) => predicate_matches(predicate, item),
+        (Segment::Kind(Kind::Attr), QueryResult::Expr(_)) => true,
+        (Segment::Kind(Kind::Block), QueryResult::Block(_)) => true,
+        (Segment::Kind(Kind::Object), QueryResult::Expr(expr)) => {
+            matches!(unparenthesized(expr), Expression::Object(_))
+        }
+        (Segment::Kind(Kind::List), QueryResult::Expr(expr)) => {
+            matches!(unparenthesized(expr), Expression::Array(_))
+        }
+        (Segment::Kind(_), _) => false,
+        _ => true,
+    }
+}
+
+/// whether `query_result` is anything other than `false` or `null`
+fn is_truthy(query_result: &QueryResult) -> bool {
+    !matches!(
+        query_result,
+        QueryResult::Expr(Expression::Bool(false) | Expression::Null)
+    )
+}
+
+fn compare(left: &QueryResult, comparison: Comparison, right: &QueryResult) -> bool {
+    // only expressions can be compared
+    let (QueryResult::Expr(left), QueryResult::Expr(right)) = (left, right) else {
+        return false;
+    };
+    let (left, right) = (Value::from(left.clone()), Value::from(right.clone()));
+    match comparison {
+        Comparison::Eq => values_equal(&left, &right),
+        Comparison::Ne => !values_equal(&left, &right),
+        Comparison::Lt => compare_values(&left, &right) == Some(Ordering::Less),
+        Comparison::Le => matches!(
+            compare_values(&left, &right),
+            Some(Ordering::Less | Ordering::Equal)
+        ),
+        Comparison::Gt => compare_values(&left, &right) == Some(Ordering::Greater),
+        Comparison::Ge => matches!(
+            compare_values(&left, &right),
+            Some(Ordering::Greater | Ordering::Equal)
+        ),
+        Comparison::Contains => match (&left, &right) {
+            (Value::String(s), Value::String(substring)) => s.contains(substring.as_str()),
+            (Value::Array(array), value) => {
+                array.iter().any(|element| values_equal(element, value))
+            }
+            (Value::Object(object), Value::String(key)) => object.contains_key(key),
+            _ => false,
+        },
+    }
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        // `1 == 1.0`
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        _ => a == b,
+    }
+}
+
+/// numbers and strings can be ordered, any other values cannot
+fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// an object key that is an identifier, unless `name` is not a valid one
+fn object_key(name: &str) -> ObjectKey {
+    Identifier::new(name).map_or_else(
+        |_| ObjectKey::Expression(Expression::from(name)),
+        ObjectKey::Identifier,
+    )
+}
+
+/// the name of an object key that is an identifier or a string
+fn object_key_name(key: &ObjectKey) -> Option<&str> {
+    match key {
+        // some objects are keyed with an Identifier
+        ObjectKey::Identifier(id) => Some(id.as_str()),
+        // some objects are keyed with a String Expression
+        ObjectKey::Expression(Expression::String(s)) => Some(s.as_str()),
+        _ => None,
+    }
+}
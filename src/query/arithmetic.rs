@@ -0,0 +1,102 @@
+//! evaluate the arithmetic operators of a filter (e.g. `+` or `%`)
+
+use std::error::Error;
+
+use hcl::{Expression, Number, Value};
+
+use super::{builtin::type_name, values_equal, QueryResult};
+use crate::{parser::Operator, parts::unparenthesized};
+
+/// apply `operator` to the values of `left` and `right`
+///
+/// `+` also concatenates strings and arrays, merges objects and ignores
+/// `null`, and `-` also removes elements from an array
+pub(super) fn arithmetic(
+    operator: Operator,
+    left: &QueryResult,
+    right: &QueryResult,
+) -> Result<Expression, Box<dyn Error>> {
+    let values = match (left, right) {
+        (QueryResult::Expr(l), QueryResult::Expr(r)) if has_value(l) && has_value(r) => {
+            (Value::from(l.clone()), Value::from(r.clone()))
+        }
+        _ => return Err(unsupported(operator, left, right)),
+    };
+    let value = match (operator, values) {
+        (Operator::Add, (Value::Null, value) | (value, Value::Null)) => value,
+        (operator, (Value::Number(l), Value::Number(r))) => {
+            Value::Number(number_arithmetic(operator, l, r)?)
+        }
+        (Operator::Add, (Value::String(l), Value::String(r))) => Value::String(l + &r),
+        (Operator::Add, (Value::Array(mut l), Value::Array(r))) => {
+            l.extend(r);
+            Value::Array(l)
+        }
+        (Operator::Add, (Value::Object(mut l), Value::Object(r))) => {
+            l.extend(r);
+            Value::Object(l)
+        }
+        (Operator::Subtract, (Value::Array(l), Value::Array(r))) => Value::Array(
+            l.into_iter()
+                .filter(|element| !r.iter().any(|removed| values_equal(element, removed)))
+                .collect(),
+        ),
+        _ => return Err(unsupported(operator, left, right)),
+    };
+    Ok(Expression::from(value))
+}
+
+/// whether `expr` has a value to compute with, unlike references, function
+/// calls and templates, which are only evaluated by terraform
+fn has_value(expr: &Expression) -> bool {
+    matches!(
+        unparenthesized(expr),
+        Expression::Null
+            | Expression::Bool(_)
+            | Expression::Number(_)
+            | Expression::String(_)
+            | Expression::Array(_)
+            | Expression::Object(_)
+    )
+}
+
+fn unsupported(operator: Operator, left: &QueryResult, right: &QueryResult) -> Box<dyn Error> {
+    format!(
+        "`{operator}` cannot be applied to {} and {}",
+        type_name(left),
+        type_name(right)
+    )
+    .into()
+}
+
+fn number_arithmetic(operator: Operator, l: Number, r: Number) -> Result<Number, Box<dyn Error>> {
+    if matches!(operator, Operator::Divide | Operator::Remainder) && r.as_f64() == Some(0.0) {
+        return Err(format!("{l} cannot be divided by zero").into());
+    }
+    if let (Some(a), Some(b)) = (l.as_i64(), r.as_i64()) {
+        let integer = match operator {
+            Operator::Add => a.checked_add(b),
+            Operator::Subtract => a.checked_sub(b),
+            Operator::Multiply => a.checked_mul(b),
+            // only a division without a remainder is an integer
+            Operator::Divide => a
+                .checked_rem(b)
+                .filter(|remainder| *remainder == 0)
+                .and_then(|_| a.checked_div(b)),
+            Operator::Remainder => a.checked_rem(b),
+        };
+        if let Some(integer) = integer {
+            return Ok(Number::from(integer));
+        }
+    }
+    // every number can be converted to a float
+    let (a, b) = (l.as_f64().unwrap(), r.as_f64().unwrap());
+    let float = match operator {
+        Operator::Add => a + b,
+        Operator::Subtract => a - b,
+        Operator::Multiply => a * b,
+        Operator::Divide => a / b,
+        Operator::Remainder => a % b,
+    };
+    Number::from_f64(float).ok_or_else(|| format!("{l} {operator} {r} is too large").into())
+}
@@ -0,0 +1,265 @@
+//! evaluate the built-in functions of a filter (e.g. `keys` or `labels`)
+
+use std::error::Error;
+
+use hcl::{Body, Expression, Identifier, Object, ObjectKey, Structure, Value};
+
+use super::{is_truthy, object_key, object_key_name, QueryResult};
+use crate::{filter::pattern::Pattern, parser::Builtin, parts::unparenthesized};
+
+/// apply `builtin` to `query_result`, which is an error if the function is not
+/// defined for that kind of result (e.g. `labels` of a string)
+///
+/// there is no result when there is nothing to return, e.g. `capture` of a
+/// string that does not match
+pub(super) fn builtin_query(
+    builtin: &Builtin,
+    query_result: &QueryResult,
+) -> Result<Option<QueryResult>, Box<dyn Error>> {
+    let expr = match builtin {
+        Builtin::Capture(pattern) => match string(query_result) {
+            Some(s) => return Ok(capture(s, pattern).map(QueryResult::Expr)),
+            None => None,
+        },
+        // like `jq`, anything other than a string is returned unchanged
+        Builtin::LtrimStr(prefix) => match string(query_result) {
+            Some(s) => Some(Expression::from(
+                s.strip_prefix(prefix.as_str()).unwrap_or(s),
+            )),
+            None => return Ok(Some(query_result.clone())),
+        },
+        builtin => defined(builtin, query_result),
+    };
+    match expr {
+        Some(expr) => Ok(Some(QueryResult::Expr(expr))),
+        None => Err(format!(
+            "`{builtin}` cannot be applied to {}",
+            type_name(query_result)
+        )
+        .into()),
+    }
+}
+
+/// the result of `builtin` for `query_result`, or `None` if the function is
+/// not defined for that kind of result
+fn defined(builtin: &Builtin, query_result: &QueryResult) -> Option<Expression> {
+    Some(match builtin {
+        Builtin::Keys => keys(query_result)?,
+        Builtin::Labels => match query_result {
+            QueryResult::Block(block) => Expression::Array(
+                block
+                    .labels()
+                    .iter()
+                    .map(|label| Expression::from(label.as_str()))
+                    .collect(),
+            ),
+            _ => return None,
+        },
+        Builtin::Ident => match query_result {
+            QueryResult::Block(block) => Expression::from(block.identifier()),
+            _ => return None,
+        },
+        Builtin::Length => length(query_result)?,
+        Builtin::Type => Expression::from(type_name(query_result)),
+        Builtin::Has(key) => Expression::Bool(has(query_result, key)?),
+        Builtin::ToEntries => to_entries(query_result)?,
+        Builtin::Not => Expression::Bool(!is_truthy(query_result)),
+        Builtin::Split(separator) => split(string(query_result)?, separator),
+        Builtin::Join(separator) => join(query_result, separator)?,
+        Builtin::Test(pattern) => Expression::Bool(pattern.is_match(string(query_result)?)),
+        Builtin::StartsWith(prefix) => {
+            Expression::Bool(string(query_result)?.starts_with(prefix.as_str()))
+        }
+        Builtin::EndsWith(suffix) => {
+            Expression::Bool(string(query_result)?.ends_with(suffix.as_str()))
+        }
+        Builtin::AsciiDowncase => Expression::from(string(query_result)?.to_ascii_lowercase()),
+        Builtin::Capture(_) | Builtin::LtrimStr(_) => {
+            unreachable!("{builtin} is applied by `builtin_query`")
+        }
+        // these navigate to what encloses the input (see `filter_query`), or
+        // are applied to all of the inputs together (see `collection_query`)
+        builtin => unreachable!("{builtin} is not applied to each input"),
+    })
+}
+
+/// the body of a block (or of the whole document)
+fn result_body(query_result: &QueryResult) -> Option<&Body> {
+    match query_result {
+        QueryResult::Body(body) => Some(body),
+        QueryResult::Block(block) => Some(block.body()),
+        QueryResult::Expr(_) => None,
+    }
+}
+
+/// the value of an expression, without the parentheses around it (e.g. the
+/// object of `x = ({ a = 1 })`)
+fn result_expr(query_result: &QueryResult) -> Option<&Expression> {
+    match query_result {
+        QueryResult::Expr(expr) => Some(unparenthesized(expr)),
+        _ => None,
+    }
+}
+
+fn structure_name(structure: &Structure) -> &str {
+    match structure {
+        Structure::Attribute(attr) => attr.key(),
+        Structure::Block(block) => block.identifier(),
+    }
+}
+
+fn keys(query_result: &QueryResult) -> Option<Expression> {
+    let keys = if let Some(body) = result_body(query_result) {
+        // blocks with the same identifier only have one key
+        let mut names: Vec<&str> = Vec::new();
+        for name in body.iter().map(structure_name) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names.into_iter().map(Expression::from).collect()
+    } else {
+        match result_expr(query_result)? {
+            Expression::Object(object) => object
+                .keys()
+                .filter_map(object_key_name)
+                .map(Expression::from)
+                .collect(),
+            Expression::Array(array) => (0..array.len() as u64).map(Expression::from).collect(),
+            _ => return None,
+        }
+    };
+    Some(Expression::Array(keys))
+}
+
+fn length(query_result: &QueryResult) -> Option<Expression> {
+    let length = match query_result {
+        QueryResult::Body(body) => body.iter().count(),
+        QueryResult::Block(block) => block.body().iter().count(),
+        QueryResult::Expr(expr) => match unparenthesized(expr) {
+            Expression::Array(array) => array.len(),
+            Expression::Object(object) => object.len(),
+            Expression::String(string) => string.chars().count(),
+            Expression::Null => 0,
+            _ => return None,
+        },
+    };
+    Some(Expression::from(length as u64))
+}
+
+pub(super) fn type_name(query_result: &QueryResult) -> &'static str {
+    match query_result {
+        QueryResult::Body(_) => "body",
+        QueryResult::Block(_) => "block",
+        QueryResult::Expr(expr) => match unparenthesized(expr) {
+            Expression::Null => "null",
+            Expression::Bool(_) => "boolean",
+            Expression::Number(_) => "number",
+            Expression::String(_) => "string",
+            Expression::Array(_) => "array",
+            Expression::Object(_) => "object",
+            // e.g. references, function calls or templates, which are only
+            // evaluated by terraform
+            _ => "expression",
+        },
+    }
+}
+
+fn has(query_result: &QueryResult, key: &Value) -> Option<bool> {
+    if let Some(body) = result_body(query_result) {
+        let key = key.as_str()?;
+        return Some(
+            body.iter()
+                .any(|structure| structure_name(structure) == key),
+        );
+    }
+    match result_expr(query_result)? {
+        Expression::Object(object) => {
+            let key = key.as_str()?;
+            Some(object.keys().any(|k| object_key_name(k) == Some(key)))
+        }
+        Expression::Array(array) => {
+            let index = key.as_u64()?;
+            Some(index < array.len() as u64)
+        }
+        _ => None,
+    }
+}
+
+fn to_entries(query_result: &QueryResult) -> Option<Expression> {
+    let entries: Vec<_> = if let Some(body) = result_body(query_result) {
+        // blocks cannot be the value of an entry, so only attributes are
+        // included
+        body.attributes()
+            .map(|attr| entry(attr.key(), attr.expr().clone()))
+            .collect()
+    } else if let Expression::Object(object) = result_expr(query_result)? {
+        object
+            .iter()
+            .filter_map(|(key, value)| Some(entry(object_key_name(key)?, value.clone())))
+            .collect()
+    } else {
+        return None;
+    };
+    Some(Expression::Array(entries))
+}
+
+fn string(query_result: &QueryResult) -> Option<&str> {
+    match result_expr(query_result)? {
+        Expression::String(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn split(s: &str, separator: &str) -> Expression {
+    let parts: Vec<_> = if separator.is_empty() {
+        // an empty separator splits a string into its characters
+        s.chars().map(|c| Expression::from(c.to_string())).collect()
+    } else {
+        s.split(separator).map(Expression::from).collect()
+    };
+    Expression::Array(parts)
+}
+
+fn join(query_result: &QueryResult, separator: &str) -> Option<Expression> {
+    let Expression::Array(array) = result_expr(query_result)? else {
+        return None;
+    };
+    let elements = array
+        .iter()
+        .map(|element| match element {
+            Expression::String(s) => Some(s.clone()),
+            Expression::Number(n) => Some(n.to_string()),
+            Expression::Bool(b) => Some(b.to_string()),
+            Expression::Null => Some(String::new()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Expression::from(elements.join(separator)))
+}
+
+/// the named groups of the first match, where a group that did not take part
+/// in the match is `null`
+fn capture(s: &str, pattern: &Pattern) -> Option<Expression> {
+    let regex = pattern.as_regex();
+    let captures = regex.captures(s)?;
+    let mut object = Object::new();
+    for name in regex.capture_names().flatten() {
+        let key = object_key(name);
+        let value = captures
+            .name(name)
+            .map_or(Expression::Null, |m| Expression::from(m.as_str()));
+        object.insert(key, value);
+    }
+    Some(Expression::Object(object))
+}
+
+fn entry(key: &str, value: Expression) -> Expression {
+    let mut entry = Object::new();
+    entry.insert(
+        ObjectKey::Identifier(Identifier::unchecked("key")),
+        Expression::from(key),
+    );
+    entry.insert(ObjectKey::Identifier(Identifier::unchecked("value")), value);
+    Expression::Object(entry)
+}
@@ -0,0 +1,305 @@
+//! evaluate the built-in functions of a filter that summarise a collection
+//! (e.g. `sort` or `group_by(f)`)
+//!
+//! like `jq`, each input is a collection on its own: a list (e.g. `.ports |
+//! sort`) or a body, which is how blocks are collected with `[...]` (e.g.
+//! `[.module] | sort_by(.source)`)
+
+use std::{cmp::Ordering, error::Error, rc::Rc};
+
+use hcl::{Block, Body, Expression, Value};
+
+use super::{
+    arithmetic::arithmetic, builtin::type_name, filter_query, located_query, Item, QueryResult,
+};
+use crate::{
+    parser::{Builtin, Filter, Operator, Segment},
+    parts::unparenthesized,
+};
+
+/// whether `builtin` is applied to a collection
+pub(super) fn is_collection(builtin: &Builtin) -> bool {
+    matches!(
+        builtin,
+        Builtin::Sort
+            | Builtin::SortBy(_)
+            | Builtin::GroupBy(_)
+            | Builtin::Unique
+            | Builtin::Min
+            | Builtin::Max
+            | Builtin::Map(_)
+            | Builtin::Add
+    )
+}
+
+/// apply `builtin` (see [`is_collection`]) to each of `items`
+pub(super) fn collection_query<'a>(
+    builtin: &Builtin,
+    items: Vec<Rc<Item<'a>>>,
+    strict: bool,
+) -> Result<Vec<Rc<Item<'a>>>, Box<dyn Error>> {
+    let mut results = Vec::new();
+    for item in items {
+        let elements = elements(&item).ok_or_else(|| {
+            format!(
+                "`{builtin}` cannot be applied to {}",
+                type_name(item.result())
+            )
+        })?;
+        results.append(&mut summarise(builtin, elements, strict)?);
+    }
+    Ok(results)
+}
+
+/// apply `builtin` to the elements of one collection
+fn summarise<'a>(
+    builtin: &Builtin,
+    elements: Vec<Rc<Item<'a>>>,
+    strict: bool,
+) -> Result<Vec<Rc<Item<'a>>>, Box<dyn Error>> {
+    let elements = match builtin {
+        Builtin::Sort => sorted(keyed(elements, None, strict)?),
+        Builtin::SortBy(filter) => sorted(keyed(elements, Some(filter), strict)?),
+        Builtin::Unique => {
+            let mut keyed = keyed(elements, None, strict)?;
+            sort(&mut keyed);
+            keyed.dedup_by(|(a, _), (b, _)| order_all(a, b).is_eq());
+            keyed.into_iter().map(|(_, item)| item).collect()
+        }
+        Builtin::Map(filter) => {
+            let mut results = Vec::new();
+            for element in elements {
+                results.append(&mut filter_query(filter, vec![element], strict)?);
+            }
+            results
+        }
+        Builtin::GroupBy(filter) => {
+            let mut keyed = keyed(elements, Some(filter), strict)?;
+            sort(&mut keyed);
+            let groups: Vec<_> = keyed
+                .chunk_by(|(a, _), (b, _)| order_all(a, b).is_eq())
+                .map(|group| {
+                    let items: Vec<_> = group.iter().map(|(_, item)| Rc::clone(item)).collect();
+                    collected(&items)
+                })
+                .collect::<Result<_, _>>()?;
+            // the groups are the elements of a list of lists, but a group of
+            // blocks (a body) cannot be part of a list, so each of those is a
+            // result on its own
+            return if groups
+                .iter()
+                .any(|group| matches!(group.result(), QueryResult::Body(_)))
+            {
+                Ok(groups)
+            } else {
+                Ok(vec![list(&groups)?])
+            };
+        }
+        Builtin::Min | Builtin::Max => {
+            let compare = |a: &Rc<Item<'a>>, b: &Rc<Item<'a>>| order(a.result(), b.result());
+            let extreme = match builtin {
+                Builtin::Min => elements.into_iter().min_by(compare),
+                _ => elements.into_iter().max_by(compare),
+            };
+            return Ok(vec![single(extreme)]);
+        }
+        Builtin::Add => {
+            let mut sum: Option<QueryResult> = None;
+            for element in &elements {
+                sum = Some(match sum {
+                    Some(sum) => {
+                        QueryResult::Expr(arithmetic(Operator::Add, &sum, element.result())?)
+                    }
+                    None => element.result().clone(),
+                });
+            }
+            return Ok(vec![single(sum.map(Item::new))]);
+        }
+        builtin => unreachable!("{builtin} does not summarise a collection"),
+    };
+    Ok(vec![collected(&elements)?])
+}
+
+/// the elements of a list, or the blocks and attribute values of a body
+/// (nothing else is a collection)
+fn elements<'a>(item: &Rc<Item<'a>>) -> Option<Vec<Rc<Item<'a>>>> {
+    match item.result() {
+        QueryResult::Body(_) => Some(
+            located_query(&Segment::Wildcard, item)
+                .into_iter()
+                .map(|(_, element)| element)
+                .collect(),
+        ),
+        QueryResult::Expr(expr) => match unparenthesized(expr) {
+            Expression::Array(array) => Some(
+                array
+                    .iter()
+                    .map(|element| Item::child(item, QueryResult::Expr(element.clone())))
+                    .collect(),
+            ),
+            _ => None,
+        },
+        QueryResult::Block(_) => None,
+    }
+}
+
+/// a list of the results of `items`, which have to be values
+pub(super) fn list<'a>(items: &[Rc<Item<'a>>]) -> Result<Rc<Item<'a>>, Box<dyn Error>> {
+    let elements = items
+        .iter()
+        .map(|item| match item.result() {
+            QueryResult::Expr(expr) => Ok(expr.clone()),
+            result => Err(format!(
+                "a list can only have values, not a {}",
+                type_name(result)
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Item::new(QueryResult::Expr(Expression::Array(elements))))
+}
+
+/// the results of `items` together: blocks are a body (like a document with
+/// only those blocks), and anything else is a list
+pub(super) fn collected<'a>(items: &[Rc<Item<'a>>]) -> Result<Rc<Item<'a>>, Box<dyn Error>> {
+    let blocks: Option<Vec<_>> = items
+        .iter()
+        .map(|item| match item.result() {
+            QueryResult::Block(block) => Some(block.clone()),
+            _ => None,
+        })
+        .collect();
+    match blocks {
+        Some(blocks) if !blocks.is_empty() => Ok(Item::new(QueryResult::Body(Body::from(blocks)))),
+        _ => list(items),
+    }
+}
+
+/// the result of a function that returns one element, which is `null` for an
+/// empty collection
+fn single<'a>(item: Option<Rc<Item<'a>>>) -> Rc<Item<'a>> {
+    item.unwrap_or_else(|| Item::new(QueryResult::Expr(Expression::Null)))
+}
+
+/// an element and what it is ordered by
+type Keyed<'a> = (Vec<QueryResult>, Rc<Item<'a>>);
+
+/// each of `items` with the results of `filter` for it (or with itself,
+/// without a filter)
+fn keyed<'a>(
+    items: Vec<Rc<Item<'a>>>,
+    filter: Option<&Filter>,
+    strict: bool,
+) -> Result<Vec<Keyed<'a>>, Box<dyn Error>> {
+    items
+        .into_iter()
+        .map(|item| {
+            let key = match filter {
+                Some(filter) => filter_query(filter, vec![Rc::clone(&item)], strict)?
+                    .iter()
+                    .map(|key| key.result().clone())
+                    .collect(),
+                None => vec![item.result().clone()],
+            };
+            Ok((key, item))
+        })
+        .collect()
+}
+
+/// sort by key, keeping elements with the same key in their order
+fn sort<'a>(keyed: &mut [Keyed<'a>]) {
+    keyed.sort_by(|(a, _), (b, _)| order_all(a, b));
+}
+
+fn sorted<'a>(mut keyed: Vec<Keyed<'a>>) -> Vec<Rc<Item<'a>>> {
+    sort(&mut keyed);
+    keyed.into_iter().map(|(_, item)| item).collect()
+}
+
+fn order_all(a: &[QueryResult], b: &[QueryResult]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| order(a, b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+/// values first (see [`value_order`]), then blocks (by identifier and then
+/// labels) and then bodies
+fn order(a: &QueryResult, b: &QueryResult) -> Ordering {
+    match (a, b) {
+        (QueryResult::Expr(a), QueryResult::Expr(b)) => {
+            value_order(&Value::from(a.clone()), &Value::from(b.clone()))
+        }
+        (QueryResult::Block(a), QueryResult::Block(b)) => {
+            a.identifier()
+                .cmp(b.identifier())
+                .then_with(|| labels(a).cmp(labels(b)))
+                // blocks that only differ by their contents are still
+                // different, e.g. for `unique`
+                .then_with(|| formatted(a.body()).cmp(&formatted(b.body())))
+        }
+        (QueryResult::Body(a), QueryResult::Body(b)) => formatted(a).cmp(&formatted(b)),
+        (a, b) => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn labels(block: &Block) -> impl Iterator<Item = &str> {
+    block.labels().iter().map(|label| label.as_str())
+}
+
+fn rank(query_result: &QueryResult) -> u8 {
+    match query_result {
+        QueryResult::Expr(_) => 0,
+        QueryResult::Block(_) => 1,
+        QueryResult::Body(_) => 2,
+    }
+}
+
+fn formatted(body: &Body) -> String {
+    hcl::format::to_string(body).unwrap_or_default()
+}
+
+/// the order of `jq`: `null`, `false`, `true`, numbers, strings, arrays (by
+/// their elements) and objects (by their sorted keys and then the values of
+/// those keys)
+fn value_order(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| value_order(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(a), Value::Object(b)) => {
+            let mut a_keys: Vec<_> = a.keys().collect();
+            let mut b_keys: Vec<_> = b.keys().collect();
+            a_keys.sort();
+            b_keys.sort();
+            a_keys.cmp(&b_keys).then_with(|| {
+                a_keys
+                    .iter()
+                    .map(|key| value_order(&a[*key], &b[*key]))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        (a, b) => value_rank(a).cmp(&value_rank(b)),
+    }
+}
+
+fn value_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
@@ -0,0 +1,140 @@
+//! suggest what a field that matches nothing was meant to be, e.g. `.variable`
+//! for `.varible`
+
+use hcl::{Body, Expression, Structure};
+use strsim::levenshtein;
+
+use super::{object_key_name, QueryResult};
+use crate::{
+    parser::{Field, Label, Segment},
+    parts::unparenthesized,
+};
+
+/// at most this many suggestions are made
+const MAX_SUGGESTIONS: usize = 3;
+
+/// the fields (closest first) similar to `segment` that would have matched an
+/// attribute, block or object key of `query_results`
+pub(super) fn suggestions(segment: &Segment, query_results: &[QueryResult]) -> Vec<Segment> {
+    // a pattern is not a typo of a name
+    let Segment::Field(field @ Field { pattern: None, .. }) = segment else {
+        return Vec::new();
+    };
+    let mut candidates = Vec::new();
+    for query_result in query_results {
+        match query_result {
+            QueryResult::Body(body) => body_candidates(field, body, &mut candidates),
+            QueryResult::Block(block) => body_candidates(field, block.body(), &mut candidates),
+            QueryResult::Expr(expr) if field.labels.is_empty() => {
+                if let Expression::Object(object) = unparenthesized(expr) {
+                    for key in object.keys() {
+                        candidates.extend(
+                            object_key_name(key).and_then(|key| name_candidate(field, key)),
+                        );
+                    }
+                }
+            }
+            QueryResult::Expr(_) => {}
+        }
+    }
+
+    // the closest candidates first, and otherwise in document order
+    candidates.sort_by_key(|(distance, _)| *distance);
+    let mut suggestions: Vec<Segment> = Vec::new();
+    for (_, candidate) in candidates {
+        if !suggestions.contains(&candidate) && suggestions.len() < MAX_SUGGESTIONS {
+            suggestions.push(candidate);
+        }
+    }
+    suggestions
+}
+
+/// e.g. "did you mean `.a`, `.b` or `.c`?"
+pub(super) fn did_you_mean(suggestions: &[Segment]) -> Option<String> {
+    let suggestions: Vec<_> = suggestions
+        .iter()
+        .map(|segment| format!("`{segment}`"))
+        .collect();
+    let list = match suggestions.as_slice() {
+        [] => return None,
+        [suggestion] => suggestion.clone(),
+        [suggestions @ .., last] => format!("{} or {last}", suggestions.join(", ")),
+    };
+    Some(format!("did you mean {list}?"))
+}
+
+fn body_candidates(field: &Field, body: &Body, candidates: &mut Vec<(usize, Segment)>) {
+    for structure in body.iter() {
+        match structure {
+            // an attribute does not have labels
+            Structure::Attribute(attr) if field.labels.is_empty() && !field.label_prefix => {
+                candidates.extend(name_candidate(field, attr.key()));
+            }
+            Structure::Attribute(_) => {}
+            Structure::Block(block) if block.identifier() == field.name => {
+                let labels: Vec<_> = block.labels().iter().map(|label| label.as_str()).collect();
+                candidates.extend(label_candidate(field, &labels));
+            }
+            Structure::Block(block) => candidates.extend(name_candidate(field, block.identifier())),
+        }
+    }
+}
+
+/// `field` with the name `name` (and the same labels), if the names are
+/// similar
+fn name_candidate(field: &Field, name: &str) -> Option<(usize, Segment)> {
+    let distance = levenshtein(&field.name, name);
+    if distance > threshold(field.name.chars().count()) {
+        return None;
+    }
+    let candidate = Field {
+        name: name.to_owned(),
+        ..field.clone()
+    };
+    Some((distance, candidate.into()))
+}
+
+/// `field` with the labels of a block that has the same name, if the labels
+/// are similar
+fn label_candidate(field: &Field, block_labels: &[&str]) -> Option<(usize, Segment)> {
+    if field.labels.len() > block_labels.len() {
+        return None;
+    }
+    let mut distance = 0;
+    let mut len = 0;
+    let mut labels = Vec::new();
+    for (label, block_label) in field.labels.iter().zip(block_labels) {
+        match label {
+            Label::Exact(label) => {
+                distance += levenshtein(label, block_label);
+                len += label.chars().count();
+                labels.push(Label::Exact((*block_label).to_owned()));
+            }
+            Label::Any => labels.push(Label::Any),
+            // a pattern is not a typo of a label
+            Label::Pattern(_) => return None,
+        }
+    }
+    // without `...`, every label of the block has to be given (and each one
+    // that is missing counts as a single difference)
+    if !field.label_prefix {
+        for block_label in &block_labels[field.labels.len()..] {
+            distance += 1;
+            labels.push(Label::Exact((*block_label).to_owned()));
+        }
+    }
+    if distance > threshold(len) {
+        return None;
+    }
+    let candidate = Field {
+        labels,
+        ..field.clone()
+    };
+    Some((distance, candidate.into()))
+}
+
+/// how many characters of a name (or labels) of length `len` can be
+/// different for it to still be suggested
+fn threshold(len: usize) -> usize {
+    (len / 3).max(1)
+}
@@ -0,0 +1,233 @@
+//! follow a filter segment by segment, to explain why it returns what it does
+
+use std::{error::Error, ops::Range, rc::Rc};
+
+use hcl::Body;
+use hcl_edit::{
+    expr::Expression,
+    structure::{self, Structure},
+    Span,
+};
+
+use super::{filter_query, located_query, Item, QueryResult};
+use crate::{
+    parser::{resolve_index, Builtin, Filter, Segment},
+    parts::{edit_parts, object_key_name},
+};
+
+/// a segment (or a filter that is not a path) and everything it returned
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// the segment as a filter of its own, or the whole filter when it is not
+    /// a path (e.g. `length` or `.a + .b`)
+    pub filter: Filter,
+    /// what the step returned, for all of its inputs
+    pub results: Vec<Traced>,
+}
+
+impl Step {
+    /// how many results are expressions
+    pub fn exprs(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|traced| matches!(traced.result, QueryResult::Expr(_)))
+            .count()
+    }
+
+    /// how many results are blocks
+    pub fn blocks(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|traced| matches!(traced.result, QueryResult::Block(_)))
+            .count()
+    }
+}
+
+/// a result of a [`Step`] and where it is in the document
+#[derive(Clone, Debug)]
+pub struct Traced {
+    /// the path (of segments that match exactly this result) from the root of
+    /// the document, or `None` when the result is not part of the document
+    /// (e.g. it was computed by `length`) or it is not known where it is
+    pub location: Option<Vec<Segment>>,
+    pub result: QueryResult,
+}
+
+/// apply `filter` to `body` one step at a time, keeping the results of each
+/// step (see [`Step`])
+///
+/// a path has a step for each of its segments, and `|` and `,` trace each of
+/// their filters, but any other filter is a single step
+///
+/// a field or index that matches nothing is not an error, since the point is
+/// to find out where the results went missing
+pub fn trace(filter: &Filter, body: &Body) -> Result<Vec<Step>, Box<dyn Error>> {
+    let document = (Some(Vec::new()), Item::document(body));
+    let mut steps = Vec::new();
+    trace_filter(filter, vec![document], &mut steps)?;
+    Ok(steps)
+}
+
+/// a result while it is traced, with its location (see [`Traced`])
+type Input<'a> = (Option<Vec<Segment>>, Rc<Item<'a>>);
+
+fn trace_filter<'a>(
+    filter: &Filter,
+    inputs: Vec<Input<'a>>,
+    steps: &mut Vec<Step>,
+) -> Result<Vec<Input<'a>>, Box<dyn Error>> {
+    match filter {
+        // the identity filter is a step too, so that it is not left out
+        Filter::Path(segments) if segments.is_empty() => {
+            steps.push(step(filter.clone(), &inputs));
+            Ok(inputs)
+        }
+        Filter::Path(segments) => Ok(segments.iter().fold(inputs, |inputs, segment| {
+            let results = trace_segment(segment, &inputs);
+            steps.push(step(Filter::Path(vec![segment.clone()]), &results));
+            results
+        })),
+        Filter::Pipe(filters) => filters
+            .iter()
+            .try_fold(inputs, |inputs, filter| trace_filter(filter, inputs, steps)),
+        Filter::Comma(filters) => {
+            let mut results = Vec::new();
+            for filter in filters {
+                results.append(&mut trace_filter(filter, inputs.clone(), steps)?);
+            }
+            Ok(results)
+        }
+        _ => {
+            let mut results = Vec::new();
+            for (location, item) in inputs {
+                let items = filter_query(filter, vec![item], false)?;
+                for (index, item) in items.into_iter().enumerate() {
+                    results.push((enclosing_location(filter, &location, index), item));
+                }
+            }
+            steps.push(step(filter.clone(), &results));
+            Ok(results)
+        }
+    }
+}
+
+fn trace_segment<'a>(segment: &Segment, inputs: &[Input<'a>]) -> Vec<Input<'a>> {
+    let mut results = Vec::new();
+    for (location, item) in inputs {
+        for (path, item) in located_query(segment, item) {
+            let location = location.clone().map(|mut location| {
+                location.extend(path);
+                location
+            });
+            results.push((location, item));
+        }
+    }
+    results
+}
+
+/// the location of the `index`-th result of `filter` (which is not a path),
+/// which is only known when it encloses the input (e.g. `parent`)
+fn enclosing_location(
+    filter: &Filter,
+    location: &Option<Vec<Segment>>,
+    index: usize,
+) -> Option<Vec<Segment>> {
+    // each enclosing result is one segment further up
+    let up = match filter {
+        Filter::Builtin(Builtin::Parent) => 1,
+        Filter::Builtin(Builtin::Ancestors) => index + 1,
+        _ => return None,
+    };
+    let location = location.as_ref()?;
+    Some(location[..location.len().checked_sub(up)?].to_vec())
+}
+
+fn step<'a>(filter: Filter, results: &[Input<'a>]) -> Step {
+    let results = results
+        .iter()
+        .map(|(location, item)| Traced {
+            location: location.clone(),
+            result: item.result().clone(),
+        })
+        .collect();
+    Step { filter, results }
+}
+
+/// the (byte) ranges of `body` that `location` (see [`Traced::location`])
+/// points at, in document order
+///
+/// there is more than one when blocks have the same identifier and labels,
+/// in which case the n-th block of a query is at the n-th range
+///
+/// `body` has to be parsed (e.g. with `str::parse`) for it to have ranges
+pub fn locate(location: &[Segment], body: &structure::Body) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    locate_in_body(location, body, &mut ranges);
+    ranges
+}
+
+fn locate_in_body(location: &[Segment], body: &structure::Body, ranges: &mut Vec<Range<usize>>) {
+    let Some((segment, rest)) = location.split_first() else {
+        ranges.extend(body.span());
+        return;
+    };
+    for structure in body.iter() {
+        match structure {
+            Structure::Attribute(attr) => {
+                if segment.matches_attr(attr.key.as_str()) {
+                    locate_in_expr(rest, &attr.value, ranges);
+                }
+            }
+            Structure::Block(block) => {
+                let labels = block.labels.iter().map(|label| label.as_str());
+                if segment.matches_block(block.ident.as_str(), labels) {
+                    match rest {
+                        [] => ranges.extend(block.span()),
+                        rest => locate_in_body(rest, &block.body, ranges),
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn locate_in_expr(location: &[Segment], expr: &Expression, ranges: &mut Vec<Range<usize>>) {
+    let Some((segment, rest)) = location.split_first() else {
+        ranges.extend(expr.span());
+        return;
+    };
+    match (segment, expr) {
+        (Segment::Index(index), Expression::Array(array)) => {
+            if let Some(element) = resolve_index(*index, array.len()).and_then(|i| array.get(i)) {
+                locate_in_expr(rest, element, ranges);
+            }
+        }
+        // a slice is part of the array it was taken from
+        (Segment::Slice(..), Expression::Array(_)) => ranges.extend(expr.span()),
+        // parentheses are not part of a location
+        (_, Expression::Parenthesis(parenthesis)) => {
+            locate_in_expr(location, parenthesis.inner(), ranges)
+        }
+        (_, Expression::Object(object)) => {
+            for (key, value) in object.iter() {
+                let matches = match segment {
+                    // a computed key can only be matched by `.*`
+                    Segment::Wildcard => object_key_name(key).is_none(),
+                    _ => segment.matches_object_key(object_key_name(key)),
+                };
+                if matches {
+                    locate_in_expr(rest, value.expr(), ranges);
+                }
+            }
+        }
+        (_, expr) => {
+            let parts = edit_parts(expr);
+            let count = parts.len();
+            for (part, value) in parts {
+                if part.matches(segment, count) {
+                    locate_in_expr(rest, value, ranges);
+                }
+            }
+        }
+    }
+}
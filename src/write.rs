@@ -1,124 +1,352 @@
 //! use the [`hcl-edit`][hcl_edit] crate to modify HCL documents
 
+use std::error::Error;
+
 use hcl_edit::{
-    expr::Expression,
+    expr::{Array, Expression, Object, ObjectKey, ObjectValue},
     structure::{Attribute, Body, Structure},
     visit_mut::VisitMut,
     Decorate, Decorated, Ident,
 };
 
-use crate::parser::Field;
+use crate::{
+    parser::{resolve_index, resolve_slice, without_optional, Field, Filter, Segment},
+    parts::{edit_parts_mut, expr_result, object_key_name, slice_result, Cursor},
+    query::qualifier_matches,
+};
 
 struct HclEditor<'a> {
-    fields: Vec<Field>,
-    current_index: usize,
-    current: Option<Field>,
+    cursor: Cursor,
     value: &'a Expression,
 }
 
-impl<'a> HclEditor<'a> {
-    fn new(fields: Vec<Field>, value: &'a Expression) -> Self {
-        let current = fields.first().cloned();
-        HclEditor {
-            fields,
-            current_index: 0,
-            current,
-            value,
-        }
+impl HclEditor<'_> {
+    /// whether a new attribute (or object key) with the value would satisfy
+    /// the qualifiers of the current segment
+    ///
+    /// it cannot satisfy a predicate, so it is only added when there is none,
+    /// but it can be of a kind (e.g. `:attr`)
+    fn adds(&self) -> bool {
+        let value = expr_result(self.value);
+        self.cursor.qualifiers().all(|qualifier| {
+            matches!(qualifier, Segment::Kind(_)) && qualifier_matches(qualifier, &value)
+        })
     }
 
-    fn current_field(&self) -> Option<Field> {
-        self.fields.get(self.current_index).cloned()
+    /// the kind qualifier of the current segment (e.g. `:list`) that the value
+    /// is not of, so that it cannot be written there
+    fn mismatched_kind(&self) -> Option<&Segment> {
+        let value = expr_result(self.value);
+        self.cursor.qualifiers().find(|qualifier| {
+            matches!(qualifier, Segment::Kind(_)) && !qualifier_matches(qualifier, &value)
+        })
     }
 
-    fn next_field(&mut self) {
-        self.current_index += 1;
-        self.current = self.current_field();
+    /// an error for a value that was not written, because it is not of the
+    /// kind of the current segment
+    fn fail_mismatched_kind(&mut self) {
+        if let Some(kind) = self.mismatched_kind() {
+            let message = format!(
+                "cannot write to `{}`, since the value is not of kind `{kind}`",
+                self.cursor.qualified()
+            );
+            self.cursor.fail(message);
+        }
     }
 
-    fn previous_field(&mut self) {
-        self.current_index -= 1;
-        self.current = self.current_field();
+    /// an error for a value that was not written, because the current
+    /// segment is a block
+    fn fail_block(&mut self) {
+        let message = format!(
+            "`{}` is a block, which cannot be replaced with a value",
+            self.cursor.qualified()
+        );
+        self.cursor.fail(message);
     }
 
-    fn should_edit(&self) -> bool {
-        self.current_index >= self.fields.len() - 1
+    /// a copy of the new value that keeps the decor (whitespace and comments)
+    /// of the `existing` value it is replacing
+    fn value_decorated_like(&self, existing: &Expression) -> Expression {
+        let mut value = self.value.clone();
+        *value.decor_mut() = existing.decor().clone();
+        value
     }
 }
 
 impl VisitMut for HclEditor<'_> {
     fn visit_body_mut(&mut self, node: &mut Body) {
-        if let Some(current) = self.current.clone() {
-            let mut matching_attr_keys = Vec::new();
-            let mut matching_block_idents = Vec::new();
+        if let Some(current) = self.cursor.current() {
+            let mut matching_attrs = Vec::new();
+            let mut matching_blocks = Vec::new();
             // save this in case we are adding new attributes
             let mut decor = None;
-            for item in node.iter() {
+            for (index, item) in node.iter().enumerate() {
                 match item {
                     Structure::Attribute(attr) => {
                         // copy existing attribute's decor
                         decor = Some(attr.decor().clone());
-                        if attr.key.as_str() == current.name {
-                            matching_attr_keys.push(attr.key.to_string());
+                        if self.cursor.selects_attr(attr) {
+                            matching_attrs.push(index);
                         }
                     }
                     Structure::Block(block) => {
-                        if block.ident.as_str() == current.name {
-                            if current.labels.is_empty() {
-                                matching_block_idents.push(block.ident.to_string());
-                            } else {
-                                for filter_label in &current.labels {
-                                    for block_label in &block.labels {
-                                        if block_label.as_str() == filter_label {
-                                            matching_block_idents.push(block.ident.to_string());
-                                        }
-                                    }
-                                }
-                            }
+                        if let Some(steps) = self.cursor.selects_block(block) {
+                            matching_blocks.push((index, steps));
                         }
                     }
                 }
             }
 
-            for key in matching_attr_keys {
-                self.next_field();
-                self.visit_attr_mut(node.get_attribute_mut(&key).unwrap());
-                self.previous_field();
+            if matching_attrs.is_empty() && matching_blocks.is_empty() && !self.cursor.is_last() {
+                self.cursor.missing();
+            }
+            if self.cursor.is_last() && matching_attrs.is_empty() {
+                if !matching_blocks.is_empty() {
+                    self.fail_block();
+                } else if !self.adds() {
+                    self.fail_mismatched_kind();
+                } else if let Segment::Field(field @ Field { pattern: None, .. }) = current {
+                    // only a named field (that is an identifier) can be added
+                    // as a new attribute, a pattern has no name to add
+                    if node
+                        .blocks()
+                        .any(|block| block.ident.as_str() == field.name)
+                    {
+                        // e.g. `.lifecycle:attr` next to a `lifecycle` block
+                        let message = format!(
+                            "cannot add `{}`, since there is a block with the same name",
+                            self.cursor.qualified()
+                        );
+                        self.cursor.fail(message);
+                    } else if let Ok(ident) = Ident::try_new(field.name) {
+                        let key = Decorated::new(ident);
+                        // copy existing attribute's decor when creating the new attribute
+                        let decor = decor.unwrap_or_default();
+                        let attr = Attribute::new(key, self.value.clone()).decorated(decor);
+                        node.insert(node.len(), attr);
+                    }
+                }
             }
 
-            for ident in matching_block_idents {
-                for block in node.get_blocks_mut(&ident) {
-                    self.next_field();
-                    self.visit_body_mut(&mut block.body);
-                    self.previous_field();
+            for index in matching_attrs {
+                // Index was gotten iterating over the node, so it must be an attribute.
+                let attr = node
+                    .get_mut(index)
+                    .and_then(Structure::as_attribute_mut)
+                    .unwrap();
+                if self.cursor.is_last() {
+                    attr.value = self.value.clone();
+                } else {
+                    self.cursor.next_segment();
+                    self.visit_expr_mut(&mut attr.value);
+                    self.cursor.previous_segment();
                 }
             }
 
-            if self.should_edit() {
-                let key = Decorated::new(Ident::new(current.name));
-                // copy existing attribute's decor when creating the new attribute
-                let decor = decor.unwrap_or_default();
-                let attr = Attribute::new(key, self.value.clone()).decorated(decor);
-                node.insert(node.len(), attr);
+            // a block cannot be replaced with a value, only traversed
+            if !self.cursor.is_last() {
+                for (index, steps) in matching_blocks {
+                    // Index was gotten iterating over the node, so it must be a block.
+                    let block = node
+                        .get_mut(index)
+                        .and_then(Structure::as_block_mut)
+                        .unwrap();
+                    self.cursor.skip(steps);
+                    if self.cursor.is_last() {
+                        // e.g. `.resource.*`, which is every resource block
+                        self.fail_block();
+                    } else {
+                        self.cursor.next_segment();
+                        self.visit_body_mut(&mut block.body);
+                        self.cursor.previous_segment();
+                    }
+                    self.cursor.unskip(steps);
+                }
             }
         }
     }
 
-    fn visit_attr_mut(&mut self, mut node: hcl_edit::structure::AttributeMut) {
-        if self.should_edit() {
-            let value = node.value_mut();
-            *value = self.value.clone();
-        } else {
-            self.next_field();
-            self.visit_expr_mut(node.value_mut());
-            self.previous_field();
+    fn visit_expr_mut(&mut self, node: &mut Expression) {
+        match node {
+            Expression::Array(array) => self.visit_array_mut(array),
+            Expression::Object(object) => self.visit_object_mut(object),
+            Expression::Parenthesis(parenthesis) => self.visit_expr_mut(parenthesis.inner_mut()),
+            // e.g. the arguments of a function call
+            node => {
+                let Some(current) = self.cursor.current() else {
+                    return;
+                };
+                let parts = edit_parts_mut(node);
+                let count = parts.len();
+                let mut matched = false;
+                for (part, expr) in parts {
+                    if !part.matches(&current, count) || !self.cursor.selects(|| expr_result(expr))
+                    {
+                        continue;
+                    }
+                    matched = true;
+                    if self.cursor.is_last() {
+                        *expr = self.value_decorated_like(expr);
+                    } else {
+                        self.cursor.next_segment();
+                        self.visit_expr_mut(expr);
+                        self.cursor.previous_segment();
+                    }
+                }
+                if !matched {
+                    self.cursor.missing();
+                }
+            }
+        }
+    }
+
+    fn visit_array_mut(&mut self, node: &mut Array) {
+        match self.cursor.current() {
+            Some(Segment::Index(index)) => {
+                let index = resolve_index(index, node.len()).filter(|index| {
+                    node.get(*index)
+                        .is_some_and(|element| self.cursor.selects(|| expr_result(element)))
+                });
+                let Some(index) = index else {
+                    self.cursor.missing();
+                    return;
+                };
+                // Index was resolved against the array's length, so it must be a non-None value.
+                let element = node.get_mut(index).unwrap();
+                if self.cursor.is_last() {
+                    *element = self.value_decorated_like(element);
+                } else {
+                    self.cursor.next_segment();
+                    self.visit_expr_mut(element);
+                    self.cursor.previous_segment();
+                }
+            }
+            Some(Segment::Slice(start, end)) => {
+                let range = resolve_slice(start, end, node.len());
+                if !self.cursor.selects(|| slice_result(node, range.clone())) {
+                    return;
+                }
+                if self.cursor.is_last() {
+                    // splice the new value(s) in place of the slice
+                    let new_elements = match self.value {
+                        Expression::Array(array) => array.iter().cloned().collect(),
+                        value => vec![value.clone()],
+                    };
+                    let mut removed = Vec::new();
+                    for index in range.clone().rev() {
+                        removed.insert(0, node.remove(index));
+                    }
+                    for (offset, mut element) in new_elements.into_iter().enumerate() {
+                        // keep the decor of the element being replaced at
+                        // this position (or the last one, if there are more
+                        // new elements than replaced ones)
+                        if let Some(existing) = removed.get(offset).or(removed.last()) {
+                            *element.decor_mut() = existing.decor().clone();
+                        }
+                        node.insert(range.start + offset, element);
+                    }
+                } else {
+                    self.cursor
+                        .fail("a slice must be the last segment of a write filter".into());
+                }
+            }
+            Some(Segment::Iterate) => {
+                for element in node.iter_mut() {
+                    if !self.cursor.selects(|| expr_result(element)) {
+                        continue;
+                    }
+                    if self.cursor.is_last() {
+                        *element = self.value_decorated_like(element);
+                    } else {
+                        self.cursor.next_segment();
+                        self.visit_expr_mut(element);
+                        self.cursor.previous_segment();
+                    }
+                }
+            }
+            // e.g. a field, which an array does not have
+            _ => self.cursor.missing(),
+        }
+    }
+
+    fn visit_object_mut(&mut self, node: &mut Object) {
+        if let Some(current) = self.cursor.current() {
+            let mut matches = Vec::new();
+            for (key, value) in node.iter() {
+                if current.matches_object_key(object_key_name(key))
+                    && self.cursor.selects(|| expr_result(value.expr()))
+                {
+                    matches.push(key.clone());
+                }
+            }
+
+            if matches.is_empty() && !self.cursor.is_last() {
+                self.cursor.missing();
+            }
+            if self.cursor.is_last() && matches.is_empty() {
+                if !self.adds() {
+                    self.fail_mismatched_kind();
+                } else if let Segment::Field(field @ Field { pattern: None, .. }) = current {
+                    // only a named field (not a pattern) can be added as a new key
+                    let key = match Ident::try_new(&field.name) {
+                        Ok(ident) => ObjectKey::Ident(Decorated::new(ident)),
+                        Err(_) => ObjectKey::Expression(Expression::from(field.name)),
+                    };
+                    node.insert(key, ObjectValue::new(self.value.clone()));
+                }
+            }
+
+            for key in matches {
+                // Every key in this vec was gotten by iterating over this object, so the value
+                // should exist.
+                let value = node.get_mut(&key).unwrap();
+                if self.cursor.is_last() {
+                    *value.expr_mut() = self.value_decorated_like(value.expr());
+                } else {
+                    self.cursor.next_segment();
+                    self.visit_object_value_mut(value);
+                    self.cursor.previous_segment();
+                }
+            }
         }
     }
 }
 
-/// given a vector of [`Field`]s, write `value` to replace the existing
-/// [`Expression`] that matches that filter
-pub fn write(fields: Vec<Field>, body: &mut Body, value: &Expression) {
-    let mut visitor = HclEditor::new(fields, value);
-    visitor.visit_body_mut(body);
+/// given a [`Filter`], write `value` to replace the existing [`Expression`]s
+/// that match that filter
+///
+/// the filter can combine paths with `|` and `,`, e.g. `.variable | .a, .b`
+/// writes to both `.variable.a` and `.variable.b`
+pub fn write(filter: &Filter, body: &mut Body, value: &Expression) -> Result<(), Box<dyn Error>> {
+    let Some(paths) = filter.paths() else {
+        return Err("write filters can only combine paths with `|` and `,`".into());
+    };
+    for segments in paths {
+        write_path(without_optional(segments), body, value)?;
+    }
+    Ok(())
+}
+
+fn write_path(
+    (segments, optional): (Vec<Segment>, Vec<bool>),
+    body: &mut Body,
+    value: &Expression,
+) -> Result<(), Box<dyn Error>> {
+    if segments.iter().all(|segment| segment.is_qualifier()) {
+        return Err("cannot write to the whole document (e.g. the identity filter `.`)".into());
+    }
+    if segments
+        .iter()
+        .any(|segment| matches!(segment, Segment::Recursive(_)))
+    {
+        return Err("recursive descent (`..`) is only supported when reading".into());
+    }
+    let mut visitor = HclEditor {
+        cursor: Cursor::new((segments, optional)),
+        value,
+    };
+    if visitor.cursor.selects_document(body) {
+        visitor.visit_body_mut(body);
+    }
+    visitor.cursor.finish()
 }
@@ -1,13 +1,18 @@
-use hq_rs::{delete, parser::Field};
+use hcl::Value;
+use hq_rs::{
+    delete,
+    filter::pattern::Pattern,
+    parser::{Comparison, Field, Filter, Kind, Label, Predicate, Segment},
+};
 
 #[test]
 fn delete_attr() {
     // filter '.version'
-    let fields = vec![Field::new("version")];
+    let filter = Filter::Path(vec![Field::new("version").into()]);
 
     let mut body = utilities::edit_hcl("version = \"test\"").expect("hcl error");
 
-    delete(fields, &mut body).expect("delete error");
+    delete(&filter, &mut body).expect("delete error");
 
     assert_eq!("", body.to_string());
 }
@@ -15,12 +20,12 @@ fn delete_attr() {
 #[test]
 fn delete_labeled_block() {
     // filter '.module{"cool-module"}'
-    let fields = vec![Field::labeled("module", &["cool-module"])];
+    let filter = Filter::Path(vec![Field::labeled("module", &["cool-module"]).into()]);
 
     let mut body =
         utilities::edit_hcl("module \"cool-module\" { version = \"1.0\" }").expect("hcl error");
 
-    delete(fields, &mut body).expect("delete error");
+    delete(&filter, &mut body).expect("delete error");
 
     assert_eq!("", body.to_string());
 }
@@ -28,15 +33,15 @@ fn delete_labeled_block() {
 #[test]
 fn delete_labeled_block_attr() {
     // filter '.module{"cool-module"}.version'
-    let fields = vec![
-        Field::labeled("module", &["cool-module"]),
-        Field::new("version"),
-    ];
+    let filter = Filter::Path(vec![
+        Field::labeled("module", &["cool-module"]).into(),
+        Field::new("version").into(),
+    ]);
 
     let mut body =
         utilities::edit_hcl("module \"cool-module\" { version = \"1.0\" }").expect("hcl error");
 
-    delete(fields, &mut body).expect("delete error");
+    delete(&filter, &mut body).expect("delete error");
 
     assert_eq!("module \"cool-module\" {}", body.to_string());
 }
@@ -44,11 +49,11 @@ fn delete_labeled_block_attr() {
 #[test]
 fn delete_block() {
     // filter '.local'
-    let fields = vec![Field::new("local")];
+    let filter = Filter::Path(vec![Field::new("local").into()]);
 
     let mut body = utilities::edit_hcl("local { var = 5 }").expect("hcl error");
 
-    delete(fields, &mut body).expect("delete error");
+    delete(&filter, &mut body).expect("delete error");
 
     assert_eq!("", body.to_string());
 }
@@ -56,11 +61,11 @@ fn delete_block() {
 #[test]
 fn delete_block_attr() {
     // filter '.local.var'
-    let fields = vec![Field::new("local"), Field::new("var")];
+    let filter = Filter::Path(vec![Field::new("local").into(), Field::new("var").into()]);
 
     let mut body = utilities::edit_hcl("local { var = 5 }").expect("hcl error");
 
-    delete(fields, &mut body).expect("delete error");
+    delete(&filter, &mut body).expect("delete error");
 
     assert_eq!("local {}", body.to_string());
 }
@@ -68,11 +73,15 @@ fn delete_block_attr() {
 #[test]
 fn delete_from_object() {
     // filter '.local.obj.val'
-    let fields = vec![Field::new("local"), Field::new("obj"), Field::new("val")];
+    let filter = Filter::Path(vec![
+        Field::new("local").into(),
+        Field::new("obj").into(),
+        Field::new("val").into(),
+    ]);
 
     let mut body = utilities::edit_hcl("local { obj = { val = 5 } }").expect("hcl error");
 
-    delete(fields, &mut body).expect("delete error");
+    delete(&filter, &mut body).expect("delete error");
 
     assert_eq!("local { obj = {} }", body.to_string());
 }
@@ -80,17 +89,317 @@ fn delete_from_object() {
 #[test]
 fn delete_from_nested_object() {
     // filter '.local.obj.obj2.val'
-    let fields = vec![
-        Field::new("local"),
-        Field::new("obj"),
-        Field::new("obj2"),
-        Field::new("val"),
-    ];
+    let filter = Filter::Path(vec![
+        Field::new("local").into(),
+        Field::new("obj").into(),
+        Field::new("obj2").into(),
+        Field::new("val").into(),
+    ]);
 
     let mut body =
         utilities::edit_hcl("local { obj = { obj2 = { val = 5 } } }").expect("hcl error");
 
-    delete(fields, &mut body).expect("delete error");
+    delete(&filter, &mut body).expect("delete error");
 
     assert_eq!("local { obj = { obj2 = {} } }", body.to_string());
 }
+
+#[test]
+fn delete_array_element() {
+    // filter '.ports[1]'
+    let filter = Filter::Path(vec![Field::new("ports").into(), Segment::Index(1)]);
+
+    let mut body = utilities::edit_hcl("ports = [80, 443, 8080]").expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("ports = [80, 8080]", body.to_string());
+}
+
+#[test]
+fn delete_array_element_out_of_range() {
+    // filter '.ports[7]'
+    let filter = Filter::Path(vec![Field::new("ports").into(), Segment::Index(7)]);
+
+    let mut body = utilities::edit_hcl("ports = [80, 443, 8080]").expect("hcl error");
+
+    let err = delete(&filter, &mut body).expect_err("out of range");
+
+    assert_eq!(
+        "`[7]` does not exist (mark it as optional with `?`, e.g. `[7]?`)",
+        err.to_string()
+    );
+    assert_eq!("ports = [80, 443, 8080]", body.to_string());
+
+    // filter '.ports[7]?'
+    let filter = Filter::Path(vec![
+        Field::new("ports").into(),
+        Segment::Index(7),
+        Segment::Optional,
+    ]);
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("ports = [80, 443, 8080]", body.to_string());
+}
+
+#[test]
+fn delete_array_slice() {
+    // filter '.ports[:-1]'
+    let filter = Filter::Path(vec![
+        Field::new("ports").into(),
+        Segment::Slice(None, Some(-1)),
+    ]);
+
+    let mut body = utilities::edit_hcl("ports = [80, 443, 8080]").expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("ports = [8080]", body.to_string());
+}
+
+#[test]
+fn delete_missing_field() {
+    // filter '.missing'
+    let filter = Filter::Path(vec![Field::new("missing").into()]);
+
+    let mut body = utilities::edit_hcl("options { enabled = false }").expect("hcl error");
+
+    let err = delete(&filter, &mut body).expect_err("missing field");
+
+    assert_eq!(
+        "`.missing` does not exist (mark it as optional with `?`, e.g. `.missing?`)",
+        err.to_string()
+    );
+
+    // filter '.options.missing?'
+    let filter = Filter::Path(vec![
+        Field::new("options").into(),
+        Field::new("missing").into(),
+        Segment::Optional,
+    ]);
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("options { enabled = false }", body.to_string());
+}
+
+#[test]
+fn delete_wildcard() {
+    // filter '.local.*'
+    let filter = Filter::Path(vec![Field::new("local").into(), Segment::Wildcard]);
+
+    let mut body = utilities::edit_hcl("local { var = 5 }").expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("local {}", body.to_string());
+}
+
+#[test]
+fn delete_wildcard_labels() {
+    let hcl = "resource \"a\" \"x\" {\n  count = 1\n  tags = {}\n}\nlocals {}";
+
+    // `.*` steps over the labels that `.resource` does not name
+    // filter '.resource.*.tags'
+    let filter = Filter::Path(vec![
+        Field::new("resource").into(),
+        Segment::Wildcard,
+        Field::new("tags").into(),
+    ]);
+    let mut body = utilities::edit_hcl(hcl).expect("hcl error");
+    delete(&filter, &mut body).expect("delete error");
+    assert_eq!(
+        "resource \"a\" \"x\" {\n  count = 1\n}\nlocals {}",
+        body.to_string()
+    );
+
+    // filter '.resource.*.*', which is each resource block
+    let filter = Filter::Path(vec![
+        Field::new("resource").into(),
+        Segment::Wildcard,
+        Segment::Wildcard,
+    ]);
+    let mut body = utilities::edit_hcl(hcl).expect("hcl error");
+    delete(&filter, &mut body).expect("delete error");
+    assert_eq!("locals {}", body.to_string());
+}
+
+#[test]
+fn delete_iterate() {
+    // filter '.ingress[].from_port'
+    let filter = Filter::Path(vec![
+        Field::new("ingress").into(),
+        Segment::Iterate,
+        Field::new("from_port").into(),
+    ]);
+
+    let mut body = utilities::edit_hcl("ingress = [{ from_port = 80 }, { from_port = 443 }]")
+        .expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("ingress = [{}, {}]", body.to_string());
+}
+
+#[test]
+fn delete_recursive() {
+    // filter '..var'
+    let filter = Filter::Path(vec![Segment::Recursive(Field::new("var"))]);
+
+    let mut body = utilities::edit_hcl("local { var = 5 }").expect("hcl error");
+
+    assert!(delete(&filter, &mut body).is_err());
+}
+
+#[test]
+fn delete_positional_labeled_block() {
+    // filter '.data{"aws_ami","ubuntu"}'
+    let filter = Filter::Path(vec![Field::labeled("data", &["aws_ami", "ubuntu"]).into()]);
+
+    let mut body = utilities::edit_hcl(
+        "data \"aws_ami\" \"ubuntu\" {}\ndata \"aws_ami\" \"other\" {}\ndata \"x\" \"ubuntu\" {}\n",
+    )
+    .expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!(
+        "data \"aws_ami\" \"other\" {}\ndata \"x\" \"ubuntu\" {}\n",
+        body.to_string()
+    );
+}
+
+#[test]
+fn delete_select() {
+    // filter '.variable.select(.sensitive == true)'
+    let filter = Filter::Path(vec![
+        Field::new("variable").into(),
+        Segment::Select(Predicate::from(
+            Filter::Path(vec![Field::new("sensitive").into()])
+                .compare(Comparison::Eq, Filter::literal(Value::Bool(true))),
+        )),
+    ]);
+
+    let mut body = utilities::edit_hcl(
+        "variable \"a\" { sensitive = true }\nvariable \"b\" { sensitive = false }\nvariable \"c\" {}\n",
+    )
+    .expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!(
+        "variable \"b\" { sensitive = false }\nvariable \"c\" {}\n",
+        body.to_string()
+    );
+}
+
+#[test]
+fn delete_comma() {
+    // filter '.version, .module.version'
+    let filter = Filter::Comma(vec![
+        Filter::Path(vec![Field::new("version").into()]),
+        Filter::Path(vec![
+            Field::new("module").into(),
+            Field::new("version").into(),
+        ]),
+    ]);
+
+    let mut body =
+        utilities::edit_hcl("version = \"1\"\nname = \"x\"\nmodule { version = \"2\" }\n")
+            .expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("name = \"x\"\nmodule {}\n", body.to_string());
+}
+
+#[test]
+fn delete_regex_labeled_block() {
+    // filter '.module{~"^APP-[0-9]+$"i}'
+    let filter = Filter::Path(vec![Field {
+        labels: vec![Label::Pattern(
+            Pattern::regex("^APP-[0-9]+$", true).unwrap(),
+        )],
+        ..Field::new("module")
+    }
+    .into()]);
+
+    let mut body =
+        utilities::edit_hcl("module \"app-1\" {}\nmodule \"app-x\" {}\nmodule \"app-23\" {}\n")
+            .expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("module \"app-x\" {}\n", body.to_string());
+}
+
+#[test]
+fn delete_dashed_attr() {
+    // filter '.cool-module'
+    let filter = Filter::Path(vec![Field::new("cool-module").into()]);
+
+    let mut body = utilities::edit_hcl("cool-module = 1\nother = 2\n").expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("other = 2\n", body.to_string());
+}
+
+#[test]
+fn delete_built_filter() {
+    // filter '.module{"prod-*"}' (with a label that contains a literal `*`)
+    let filter = Filter::root().block("module", ["prod-*"]);
+
+    let mut body =
+        utilities::edit_hcl("module \"prod-*\" {}\nmodule \"prod-a\" {}\n").expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("module \"prod-a\" {}\n", body.to_string());
+}
+
+#[test]
+fn delete_function_argument() {
+    // filter '.tags[0]'
+    let filter = Filter::root().attr("tags").index(0);
+
+    let mut body =
+        utilities::edit_hcl("tags = merge(local.tags, { Name = \"logs\" })\n").expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!("tags = merge({ Name = \"logs\" })\n", body.to_string());
+}
+
+#[test]
+fn delete_conditional_branch() {
+    // filter '.size.true'
+    let filter = Filter::root().attr("size").attr("true");
+
+    let mut body =
+        utilities::edit_hcl("size = var.prod ? \"large\" : \"small\"\n").expect("hcl error");
+
+    let err = delete(&filter, &mut body).expect_err("a branch cannot be removed");
+    assert_eq!(
+        "`.true` is a part of an expression that cannot be removed",
+        err.to_string()
+    );
+}
+
+#[test]
+fn delete_kind() {
+    // filter '.lifecycle:attr'
+    let filter = Filter::root().attr("lifecycle").kind(Kind::Attr);
+
+    let mut body =
+        utilities::edit_hcl("lifecycle = {}\nlifecycle {\n  prevent_destroy = true\n}\n")
+            .expect("hcl error");
+
+    delete(&filter, &mut body).expect("delete error");
+
+    assert_eq!(
+        "lifecycle {\n  prevent_destroy = true\n}\n",
+        body.to_string()
+    );
+}
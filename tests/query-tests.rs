@@ -1,14 +1,24 @@
-use hq_rs::{parser::Field, query};
+use hcl::Value;
+use hq_rs::{
+    filter::pattern::Pattern,
+    parser::{
+        Builtin, Comparison, Field, Filter, Kind, Label, Operator, Predicate, Segment, TemplatePart,
+    },
+    query,
+    query::{diagnose, locate, paths, trace},
+    CompiledFilter,
+};
 
 #[test]
 fn scalar_attr() {
     // filter '.version'
-    let mut fields = vec![Field::new("version")];
+    let filter = Filter::Path(vec![Field::new("version").into()]);
     let body = utilities::read_test_hcl().expect("hcl error");
 
     let expected = vec![String::from("\"test\"")];
 
-    let results: Vec<_> = query(&mut fields, &body)
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
         .iter()
         .map(|r| r.to_string().unwrap())
         .collect();
@@ -19,12 +29,13 @@ fn scalar_attr() {
 #[test]
 fn obj_attr() {
     // filter '.options'
-    let mut fields = vec![Field::new("options")];
+    let filter = Filter::Path(vec![Field::new("options").into()]);
     let body = utilities::read_test_hcl().expect("hcl error");
 
     let expected = vec![String::from("{\n  verbose = true\n  debug = false\n}")];
 
-    let results: Vec<_> = query(&mut fields, &body)
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
         .iter()
         .map(|r| r.to_string().unwrap())
         .collect();
@@ -35,7 +46,10 @@ fn obj_attr() {
 #[test]
 fn block_attr() {
     // filter '.variable.default'
-    let mut fields = vec![Field::new("variable"), Field::new("default")];
+    let filter = Filter::Path(vec![
+        Field::new("variable").into(),
+        Field::new("default").into(),
+    ]);
     let body = utilities::read_test_hcl().expect("hcl error");
 
     let expected = vec![
@@ -43,7 +57,8 @@ fn block_attr() {
         String::from("\"another_default_value\""),
     ];
 
-    let results: Vec<_> = query(&mut fields, &body)
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
         .iter()
         .map(|r| r.to_string().unwrap())
         .collect();
@@ -54,15 +69,16 @@ fn block_attr() {
 #[test]
 fn labeled_block_attr() {
     // filter '.variable{"my_var"}.default'
-    let mut fields = vec![
-        Field::labeled("variable", &["my_var"]),
-        Field::new("default"),
-    ];
+    let filter = Filter::Path(vec![
+        Field::labeled("variable", &["my_var"]).into(),
+        Field::new("default").into(),
+    ]);
     let body = utilities::read_test_hcl().expect("hcl error");
 
     let expected = vec![String::from("\"my_default_value\"")];
 
-    let results: Vec<_> = query(&mut fields, &body)
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
         .iter()
         .map(|r| r.to_string().unwrap())
         .collect();
@@ -73,7 +89,7 @@ fn labeled_block_attr() {
 #[test]
 fn block() {
     // filter '.data'
-    let mut fields = vec![Field::new("data")];
+    let filter = Filter::Path(vec![Field::new("data").into()]);
     let body = utilities::read_test_hcl().expect("hcl error");
 
     let expected = vec![
@@ -81,7 +97,8 @@ fn block() {
         String::from("data \"another_data_block\" \"with_some_attrs\" {\n  cromulent_attr = \"cromulent_value\"\n}\n"),
     ];
 
-    let results: Vec<_> = query(&mut fields, &body)
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
         .iter()
         .map(|r| r.to_string().unwrap())
         .collect();
@@ -91,13 +108,18 @@ fn block() {
 
 #[test]
 fn labeled_block() {
-    // filter '.data{"another_data_block"}'
-    let mut fields = vec![Field::labeled("data", &["another_data_block"])];
+    // filter '.data{"another_data_block", ...}'
+    let filter = Filter::Path(vec![Field {
+        label_prefix: true,
+        ..Field::labeled("data", &["another_data_block"])
+    }
+    .into()]);
     let body = utilities::read_test_hcl().expect("hcl error");
 
     let expected = vec![String::from("data \"another_data_block\" \"with_some_attrs\" {\n  cromulent_attr = \"cromulent_value\"\n}\n")];
 
-    let results: Vec<_> = query(&mut fields, &body)
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
         .iter()
         .map(|r| r.to_string().unwrap())
         .collect();
@@ -108,18 +130,1609 @@ fn labeled_block() {
 #[test]
 fn dash_labeled_block() {
     // filter '.module{"cool-module"}.version'
-    let mut fields = vec![
-        Field::labeled("module", &["cool-module"]),
-        Field::new("version"),
-    ];
+    let filter = Filter::Path(vec![
+        Field::labeled("module", &["cool-module"]).into(),
+        Field::new("version").into(),
+    ]);
     let body = utilities::read_test_hcl().expect("hcl error");
 
     let expected = vec![String::from("\"1.2.3\"")];
 
-    let results: Vec<_> = query(&mut fields, &body)
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn array_index() {
+    // filter '.ports[0]'
+    let filter = Filter::Path(vec![Field::new("ports").into(), Segment::Index(0)]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("80")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn negative_array_index() {
+    // filter '.ports[-1]'
+    let filter = Filter::Path(vec![Field::new("ports").into(), Segment::Index(-1)]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("8080")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn array_slice() {
+    // filter '.ports[1:]'
+    let filter = Filter::Path(vec![
+        Field::new("ports").into(),
+        Segment::Slice(Some(1), None),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("[\n  443,\n  8080\n]")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn array_element_attr() {
+    // filter '.ingress[1].from_port'
+    let filter = Filter::Path(vec![
+        Field::new("ingress").into(),
+        Segment::Index(1),
+        Field::new("from_port").into(),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("443")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn identity() {
+    // filter '.'
+    let filter = Filter::Path(vec![]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![hcl::format::to_string(&body).unwrap()];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn wildcard() {
+    // `.*` steps over the label of each variable, and then matches what is
+    // inside of it
+    // filter '.variable.*.*'
+    let filter = Filter::Path(vec![
+        Field::new("variable").into(),
+        Segment::Wildcard,
+        Segment::Wildcard,
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![
+        String::from("\"my_default_value\""),
+        String::from("\"another_default_value\""),
+    ];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn wildcard_nested_block() {
+    // `.*` steps over the labels that `.resource` does not name, like
+    // `resource.<type>.<name>` in JSON, before it goes further down
+    let body: hcl::Body = hcl::from_str(
+        r#"
+        resource "aws_instance" "web" {
+            tags = { name = "web" }
+            root_block_device { tags = { name = "web-root" } }
+        }
+        resource "aws_s3_bucket" "logs" { tags = { name = "logs" } }
+        "#,
+    )
+    .expect("hcl error");
+
+    let tags_name = |mut segments: Vec<Segment>| {
+        segments.push(Field::new("tags").into());
+        segments.push(Field::new("name").into());
+        Filter::Path(segments)
+    };
+    let resource = || Segment::from(Field::new("resource"));
+    let web = || {
+        let mut field = Field::labeled("resource", &["aws_instance"]);
+        field.label_prefix = true;
+        Segment::from(field)
+    };
+    let cases = [
+        // filter '.resource.tags.name'
+        (tags_name(vec![resource()]), vec!["\"web\"", "\"logs\""]),
+        // filter '.resource.*.tags.name'
+        (
+            tags_name(vec![resource(), Segment::Wildcard]),
+            vec!["\"web\"", "\"logs\""],
+        ),
+        // filter '.resource.*.*.tags.name'
+        (
+            tags_name(vec![resource(), Segment::Wildcard, Segment::Wildcard]),
+            vec!["\"web\"", "\"logs\""],
+        ),
+        // once every label is stepped over, `.*` is what is inside of a block
+        // filter '.resource.*.*.*.tags.name'
+        (
+            tags_name(vec![
+                resource(),
+                Segment::Wildcard,
+                Segment::Wildcard,
+                Segment::Wildcard,
+            ]),
+            vec!["\"web-root\""],
+        ),
+        // filter '.resource{"aws_instance", ...}.*.tags.name'
+        (tags_name(vec![web(), Segment::Wildcard]), vec!["\"web\""]),
+        // filter '.resource{"aws_instance", ...}.*.*.tags.name'
+        (
+            tags_name(vec![web(), Segment::Wildcard, Segment::Wildcard]),
+            vec!["\"web-root\""],
+        ),
+    ];
+    for (filter, expected) in cases {
+        let results: Vec<_> = query(&filter, &body)
+            .expect("query error")
+            .iter()
+            .map(|r| r.to_string().unwrap())
+            .collect();
+        assert_eq!(expected, results, "{filter}");
+    }
+}
+
+#[test]
+fn object_wildcard() {
+    // filter '.options.*'
+    let filter = Filter::Path(vec![Field::new("options").into(), Segment::Wildcard]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("true"), String::from("false")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn iterate() {
+    // filter '.ingress[].from_port'
+    let filter = Filter::Path(vec![
+        Field::new("ingress").into(),
+        Segment::Iterate,
+        Field::new("from_port").into(),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("80"), String::from("443")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn recursive() {
+    // filter '..from_port'
+    let filter = Filter::Path(vec![Segment::Recursive(Field::new("from_port"))]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("80"), String::from("443")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn recursive_block_attr() {
+    // filter '..variable.default'
+    let filter = Filter::Path(vec![
+        Segment::Recursive(Field::new("variable")),
+        Field::new("default").into(),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![
+        String::from("\"my_default_value\""),
+        String::from("\"another_default_value\""),
+    ];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn positional_labeled_block() {
+    // filter '.data{"a_data_block","with_some_attrs"}.my_attr'
+    let filter = Filter::Path(vec![
+        Field::labeled("data", &["a_data_block", "with_some_attrs"]).into(),
+        Field::new("my_attr").into(),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("\"my_attr_value\"")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn any_labeled_block() {
+    // filter '.data{*,"with_some_attrs"}'
+    let filter = Filter::Path(vec![Field {
+        labels: vec![Label::Any, Label::Exact(String::from("with_some_attrs"))],
+        ..Field::new("data")
+    }
+    .into()]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let results = query(&filter, &body).expect("query error");
+
+    assert_eq!(2, results.len());
+}
+
+#[test]
+fn select() {
+    // filter '.variable.select(.default == "my_default_value")'
+    let filter = Filter::Path(vec![
+        Field::new("variable").into(),
+        Segment::Select(Predicate::from(
+            Filter::Path(vec![Field::new("default").into()]).compare(
+                Comparison::Eq,
+                Filter::literal(Value::from("my_default_value")),
+            ),
+        )),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from(
+        "variable \"my_var\" {\n  default = \"my_default_value\"\n}\n",
+    )];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn select_elements() {
+    // filter '.ports[].select(. > 100)'
+    let filter = Filter::Path(vec![
+        Field::new("ports").into(),
+        Segment::Iterate,
+        Segment::Select(Predicate::from(
+            Filter::Path(vec![]).compare(Comparison::Gt, Filter::literal(Value::from(100))),
+        )),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("443"), String::from("8080")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn select_contains() {
+    // filter '.options.select(. contains "debug")'
+    let filter = Filter::Path(vec![
+        Field::new("options").into(),
+        Segment::Select(Predicate::from(
+            Filter::Path(vec![])
+                .compare(Comparison::Contains, Filter::literal(Value::from("debug"))),
+        )),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let results = query(&filter, &body).expect("query error");
+
+    assert_eq!(1, results.len());
+}
+
+#[test]
+fn pipe() {
+    // filter '.variable | .default'
+    let filter = Filter::Pipe(vec![
+        Filter::Path(vec![Field::new("variable").into()]),
+        Filter::Path(vec![Field::new("default").into()]),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![
+        String::from("\"my_default_value\""),
+        String::from("\"another_default_value\""),
+    ];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn comma() {
+    // filter '.version, .module.version'
+    let filter = Filter::Comma(vec![
+        Filter::Path(vec![Field::new("version").into()]),
+        Filter::Path(vec![
+            Field::new("module").into(),
+            Field::new("version").into(),
+        ]),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("\"test\""), String::from("\"1.2.3\"")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
         .iter()
         .map(|r| r.to_string().unwrap())
         .collect();
 
     assert_eq!(expected, results);
 }
+
+#[test]
+fn comma_per_input() {
+    // filter '.ingress[] | .from_port, .'
+    let filter = Filter::Pipe(vec![
+        Filter::Path(vec![Field::new("ingress").into(), Segment::Iterate]),
+        Filter::Comma(vec![
+            Filter::Path(vec![Field::new("from_port").into()]),
+            Filter::Path(vec![]),
+        ]),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![
+        String::from("80"),
+        String::from("{\n  from_port = 80\n}"),
+        String::from("443"),
+        String::from("{\n  from_port = 443\n}"),
+    ];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn builtin_labels() {
+    // filter '.variable | labels'
+    let filter = Filter::Pipe(vec![
+        Filter::Path(vec![Field::new("variable").into()]),
+        Filter::Builtin(Builtin::Labels),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![
+        String::from("[\n  \"my_var\"\n]"),
+        String::from("[\n  \"another_var\"\n]"),
+    ];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn builtin_keys() {
+    // filter '.data{"a_data_block", ...} | keys'
+    let filter = Filter::Pipe(vec![
+        Filter::Path(vec![Field {
+            label_prefix: true,
+            ..Field::labeled("data", &["a_data_block"])
+        }
+        .into()]),
+        Filter::Builtin(Builtin::Keys),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("[\n  \"my_attr\",\n  \"another_attr\"\n]")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn builtin_scalars() {
+    // filter '.ports | length, type, has(3)'
+    let filter = Filter::Pipe(vec![
+        Filter::Path(vec![Field::new("ports").into()]),
+        Filter::Comma(vec![
+            Filter::Builtin(Builtin::Length),
+            Filter::Builtin(Builtin::Type),
+            Filter::Builtin(Builtin::Has(Value::from(3))),
+        ]),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![
+        String::from("3"),
+        String::from("\"array\""),
+        String::from("false"),
+    ];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn builtin_type_error() {
+    // filter '.version | labels'
+    let filter = Filter::root().attr("version").pipe(Builtin::Labels);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let err = query(&filter, &body).expect_err("labels of a string");
+    assert_eq!("`labels` cannot be applied to string", err.to_string());
+
+    // filter '.options | has(0)'
+    let filter = Filter::root()
+        .attr("options")
+        .pipe(Builtin::Has(Value::from(0)));
+
+    let err = query(&filter, &body).expect_err("an object has no index");
+    assert_eq!("`has(0)` cannot be applied to object", err.to_string());
+}
+
+#[test]
+fn builtin_parenthesized() {
+    let body: hcl::Body =
+        hcl::from_str("x = ({ a = 1, b = 2 })\ny = ([1, 2, 3])\n").expect("hcl error");
+
+    let cases = [
+        // filter '.x | keys, length, type, has("b"), to_entries'
+        (
+            Filter::root().attr("x").pipe(
+                Filter::Builtin(Builtin::Keys)
+                    .comma(Builtin::Length)
+                    .comma(Builtin::Type)
+                    .comma(Builtin::Has(Value::from("b")))
+                    .comma(Builtin::ToEntries),
+            ),
+            vec![
+                "[\n  \"a\",\n  \"b\"\n]",
+                "2",
+                "\"object\"",
+                "true",
+                "[\n  {\n    key = \"a\"\n    value = 1\n  },\n  {\n    key = \"b\"\n    value = 2\n  }\n]",
+            ],
+        ),
+        // filter '.y | keys, length, type, has(2)'
+        (
+            Filter::root().attr("y").pipe(
+                Filter::Builtin(Builtin::Keys)
+                    .comma(Builtin::Length)
+                    .comma(Builtin::Type)
+                    .comma(Builtin::Has(Value::from(2))),
+            ),
+            vec!["[\n  0,\n  1,\n  2\n]", "3", "\"array\"", "true"],
+        ),
+        // filter '.y[0] + .x.a'
+        (
+            Filter::root()
+                .attr("y")
+                .index(0)
+                .arithmetic(Operator::Add, Filter::root().attr("x").attr("a")),
+            vec!["2"],
+        ),
+    ];
+
+    for (filter, expected) in cases {
+        let results: Vec<_> = query(&filter, &body)
+            .expect("query error")
+            .iter()
+            .map(|r| r.to_string().unwrap())
+            .collect();
+
+        assert_eq!(expected, results, "{filter}");
+    }
+}
+
+#[test]
+fn builtin_block() {
+    // filter '.module | ident, has("version"), to_entries'
+    let filter = Filter::Pipe(vec![
+        Filter::Path(vec![Field::new("module").into()]),
+        Filter::Comma(vec![
+            Filter::Builtin(Builtin::Ident),
+            Filter::Builtin(Builtin::Has(Value::from("version"))),
+            Filter::Builtin(Builtin::ToEntries),
+        ]),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![
+        String::from("\"module\""),
+        String::from("true"),
+        String::from("[\n  {\n    key = \"version\"\n    value = \"1.2.3\"\n  }\n]"),
+    ];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn glob_labeled_block() {
+    // filter '.data{"*_data_block", *} | labels'
+    let filter = Filter::Pipe(vec![
+        Filter::Path(vec![Field {
+            labels: vec![
+                Label::Pattern(Pattern::glob("*_data_block", false)),
+                Label::Any,
+            ],
+            ..Field::new("data")
+        }
+        .into()]),
+        Filter::Builtin(Builtin::Labels),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let results = query(&filter, &body).expect("query error");
+
+    assert_eq!(2, results.len());
+}
+
+#[test]
+fn regex_name() {
+    // filter '.~"^(VERSION|ports)$"i'
+    let filter = Filter::Path(vec![Field {
+        pattern: Some(Pattern::regex("^(VERSION|ports)$", true).unwrap()),
+        ..Field::new("^(VERSION|ports)$")
+    }
+    .into()]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![
+        String::from("\"test\""),
+        String::from("[\n  80,\n  443,\n  8080\n]"),
+    ];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn missing_field() {
+    // filter '.variable.nope'
+    let filter = Filter::Path(vec![
+        Field::new("variable").into(),
+        Field::new("nope").into(),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    assert!(query(&filter, &body).is_err());
+}
+
+#[test]
+fn optional_field() {
+    // filter '.variable.nope?'
+    let filter = Filter::Path(vec![
+        Field::new("variable").into(),
+        Field::new("nope").into(),
+        Segment::Optional,
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let results = query(&filter, &body).expect("query error");
+
+    assert!(results.is_empty());
+}
+
+#[test]
+fn alternative() {
+    // filter '.options.debug // .ports[5] // "default"'
+    let filter = Filter::Alternative(vec![
+        Filter::Path(vec![
+            Field::new("options").into(),
+            Field::new("debug").into(),
+        ]),
+        Filter::Path(vec![Field::new("ports").into(), Segment::Index(5)]),
+        Filter::Literal(Value::from("default")),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("\"default\"")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn alternative_existing() {
+    // filter '.version // "default"'
+    let filter = Filter::Alternative(vec![
+        Filter::Path(vec![Field::new("version").into()]),
+        Filter::Literal(Value::from("default")),
+    ]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("\"test\"")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn escaped_labeled_block() {
+    // filter '.module{"registry.example.com/team/vpc", "it\'s \"quoted\""}.source'
+    let filter = Filter::Path(vec![
+        Field::labeled(
+            "module",
+            &["registry.example.com/team/vpc", "it's \"quoted\""],
+        )
+        .into(),
+        Field::new("source").into(),
+    ]);
+    let body: hcl::Body = hcl::from_str(
+        "module \"registry.example.com/team/vpc\" \"it's \\\"quoted\\\"\" { source = \"x\" }",
+    )
+    .expect("hcl error");
+
+    let expected = vec![String::from("\"x\"")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn built_filter() {
+    // filter '.data{"a_data_block", "with_some_attrs"}.my_attr'
+    let filter = Filter::root()
+        .block("data", ["a_data_block", "with_some_attrs"])
+        .attr("my_attr");
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("\"my_attr_value\"")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn arithmetic() {
+    // filter '.ports[0] + .ports[-1] * 2, .version + "-rc"'
+    let filter = Filter::root()
+        .attr("ports")
+        .index(0)
+        .arithmetic(
+            Operator::Add,
+            Filter::root()
+                .attr("ports")
+                .index(-1)
+                .arithmetic(Operator::Multiply, Filter::literal(2)),
+        )
+        .comma(
+            Filter::root()
+                .attr("version")
+                .arithmetic(Operator::Add, Filter::literal("-rc")),
+        );
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("16240"), String::from("\"test-rc\"")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn arithmetic_type_error() {
+    // filter '.version - 1'
+    let filter = Filter::root()
+        .attr("version")
+        .arithmetic(Operator::Subtract, Filter::literal(1));
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let err = query(&filter, &body).expect_err("string minus number");
+    assert_eq!(
+        "`-` cannot be applied to string and number",
+        err.to_string()
+    );
+}
+
+#[test]
+fn comparison_and_boolean() {
+    // filter '.ports[].select(. > 100 and . < 1000), (.options | .verbose and (.debug | not))'
+    let filter = Filter::root()
+        .attr("ports")
+        .iterate()
+        .select(
+            Filter::root()
+                .compare(Comparison::Gt, Filter::literal(100))
+                .and(Filter::root().compare(Comparison::Lt, Filter::literal(1000))),
+        )
+        .comma(
+            Filter::root().attr("options").pipe(
+                Filter::root()
+                    .attr("verbose")
+                    .and(Filter::root().attr("debug").pipe(Builtin::Not)),
+            ),
+        );
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("443"), String::from("true")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn select_or_missing_field() {
+    // filter '.variable.select(.missing or .default == "another_default_value") | labels'
+    let filter = Filter::root()
+        .attr("variable")
+        .select(
+            Filter::root().attr("missing").or(Filter::root()
+                .attr("default")
+                .compare(Comparison::Eq, Filter::literal("another_default_value"))),
+        )
+        .pipe(Builtin::Labels);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let expected = vec![String::from("[\n  \"another_var\"\n]")];
+
+    let results: Vec<_> = query(&filter, &body)
+        .expect("query error")
+        .iter()
+        .map(|r| r.to_string().unwrap())
+        .collect();
+
+    assert_eq!(expected, results);
+}
+
+#[test]
+fn missing_field_suggestion() {
+    // filter '.varible{"my_var"}'
+    let filter = Filter::root().block("varible", ["my_var"]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let err = query(&filter, &body).expect_err("typo in name");
+    assert_eq!(
+        "`.varible{\"my_var\"}` does not exist (did you mean `.variable{\"my_var\"}`?)",
+        err.to_string()
+    );
+
+    // filter '.variable{"my_vr"}'
+    let filter = Filter::root().block("variable", ["my_vr"]);
+
+    let err = query(&filter, &body).expect_err("typo in label");
+    assert_eq!(
+        "`.variable{\"my_vr\"}` does not exist (did you mean `.variable{\"my_var\"}`?)",
+        err.to_string()
+    );
+
+    // filter '.tags.nme' (of an object in parentheses)
+    let filter = Filter::root().attr("tags").attr("nme");
+    let body: hcl::Body = hcl::from_str("tags = ({ name = \"x\" })").expect("hcl error");
+
+    let err = query(&filter, &body).expect_err("typo in key");
+    assert_eq!(
+        "`.nme` does not exist (did you mean `.name`?)",
+        err.to_string()
+    );
+}
+
+#[test]
+fn diagnose_empty_results() {
+    // filter '.data{"a_data_block", ...}.my_atr?, .variable.select(.default == "nope")'
+    let filter = Filter::root()
+        .field(Field {
+            label_prefix: true,
+            ..Field::labeled("data", &["a_data_block"])
+        })
+        .attr("my_atr")
+        .optional()
+        .comma(
+            Filter::root().attr("variable").select(
+                Filter::root()
+                    .attr("default")
+                    .compare(Comparison::Eq, Filter::literal("nope")),
+            ),
+        );
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    assert!(query(&filter, &body).expect("query error").is_empty());
+
+    let mismatches: Vec<_> = diagnose(&filter, &body)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+    let expected = vec![
+        String::from(
+            "`.my_atr` of `.data{\"a_data_block\", ...}.my_atr` matched nothing, did you mean `.my_attr`?",
+        ),
+        String::from(
+            "`.select(.default == \"nope\")` of `.variable.select(.default == \"nope\")` matched nothing",
+        ),
+    ];
+    assert_eq!(expected, mismatches);
+}
+
+#[test]
+fn trace_segments() {
+    // filter '(.data | .my_attr?), length'
+    let filter = Filter::root()
+        .attr("data")
+        .pipe(Filter::root().attr("my_attr").optional())
+        .comma(Filter::Builtin(Builtin::Length));
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let steps = trace(&filter, &body).expect("trace error");
+
+    let summary: Vec<_> = steps
+        .iter()
+        .map(|step| (step.filter.to_string(), step.exprs(), step.blocks()))
+        .collect();
+    let expected = vec![
+        (String::from(".data"), 0, 2),
+        (String::from(".my_attr"), 1, 0),
+        (String::from("?"), 1, 0),
+        (String::from("length"), 1, 0),
+    ];
+    assert_eq!(expected, summary);
+
+    let locations: Vec<_> = steps[1]
+        .results
+        .iter()
+        .map(|traced| Filter::Path(traced.location.clone().unwrap()).to_string())
+        .collect();
+    let expected = vec![String::from(
+        ".data{\"a_data_block\", \"with_some_attrs\"}.my_attr",
+    )];
+    assert_eq!(expected, locations);
+
+    // `length` is not part of the document
+    assert!(steps[3].results[0].location.is_none());
+}
+
+#[test]
+fn locate_results() {
+    // filter '..from_port'
+    let filter = Filter::Path(vec![Segment::Recursive(Field::new("from_port"))]);
+    let body = utilities::read_test_hcl().expect("hcl error");
+    let contents = std::fs::read_to_string("tests/test.tf").expect("read error");
+    let edit_body = utilities::edit_hcl(&contents).expect("hcl error");
+
+    let steps = trace(&filter, &body).expect("trace error");
+
+    let located: Vec<_> = steps[0]
+        .results
+        .iter()
+        .flat_map(|traced| locate(traced.location.as_ref().unwrap(), &edit_body))
+        .map(|range| &contents[range])
+        .collect();
+    assert_eq!(vec!["80", "443"], located);
+}
+
+#[test]
+fn compiled_filter_between_threads() {
+    let filter: CompiledFilter = ".data{\"a_data_block\", ...}.my_attr"
+        .parse()
+        .expect("parse error");
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let results: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| scope.spawn(|| filter.query(&body).expect("query error")))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    for results in results {
+        let results: Vec<_> = results.iter().map(|r| r.to_string().unwrap()).collect();
+        assert_eq!(vec![String::from("\"my_attr_value\"")], results);
+    }
+    // the filter is the same after it was applied
+    assert_eq!(".data{\"a_data_block\", ...}.my_attr", filter.to_string());
+}
+
+#[test]
+fn document_paths() {
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let paths: Vec<_> = paths(&body)
+        .into_iter()
+        .map(|(path, _)| Filter::Path(path).to_string())
+        .collect();
+
+    let expected = [
+        ".version",
+        ".options",
+        ".options.verbose",
+        ".options.debug",
+        ".ports",
+        ".ports[0]",
+        ".ports[1]",
+        ".ports[2]",
+        ".ingress",
+        ".ingress[0]",
+        ".ingress[0].from_port",
+        ".ingress[1]",
+        ".ingress[1].from_port",
+        ".variable{\"my_var\"}",
+        ".variable{\"my_var\"}.default",
+        ".variable{\"another_var\"}",
+        ".variable{\"another_var\"}.default",
+        ".data{\"a_data_block\", \"with_some_attrs\"}",
+        ".data{\"a_data_block\", \"with_some_attrs\"}.my_attr",
+        ".data{\"a_data_block\", \"with_some_attrs\"}.another_attr",
+        ".data{\"another_data_block\", \"with_some_attrs\"}",
+        ".data{\"another_data_block\", \"with_some_attrs\"}.cromulent_attr",
+        ".module{\"cool-module\"}",
+        ".module{\"cool-module\"}.version",
+    ];
+    assert_eq!(expected.to_vec(), paths);
+
+    // every path is a filter that returns the value it was listed with
+    for (path, query_result) in hq_rs::query::paths(&body) {
+        let filter = hq_rs::parse_filter(&Filter::Path(path).to_string()).expect("parse error");
+        let results = query(&filter, &body).expect("query error");
+        assert_eq!(1, results.len());
+        assert_eq!(
+            query_result.to_string().unwrap(),
+            results[0].to_string().unwrap()
+        );
+    }
+}
+
+#[test]
+fn computed_key_paths() {
+    // a computed key cannot be selected on its own, so it has no path
+    let body: hcl::Body = hcl::from_str("x = { (var.k) = { a = 1 }, b = 2 }").expect("hcl error");
+
+    let paths: Vec<_> = paths(&body)
+        .into_iter()
+        .map(|(path, _)| Filter::Path(path).to_string())
+        .collect();
+
+    assert_eq!(vec![".x", ".x.b"], paths);
+}
+
+#[test]
+fn expression_parts() {
+    let body: hcl::Body = hcl::from_str(
+        r#"
+        tags = merge(local.tags, { Name = "logs" })
+        size = var.prod ? "large" : "small"
+        name = "${var.prefix}-%{if var.suffix != ""}${var.suffix}%{endif}"
+        ids = { for k, v in var.things : k => v.id if v.enabled }
+        "#,
+    )
+    .expect("hcl error");
+
+    let cases = [
+        // filter '.tags[1].Name'
+        (
+            Filter::root().attr("tags").index(1).attr("Name"),
+            vec!["\"logs\""],
+        ),
+        // filter '.tags[-2]'
+        (Filter::root().attr("tags").index(-2), vec!["local.tags"]),
+        // filter '.size.true, .size.condition'
+        (
+            Filter::root()
+                .attr("size")
+                .attr("true")
+                .comma(Filter::root().attr("size").attr("condition")),
+            vec!["\"large\"", "var.prod"],
+        ),
+        // filter '.name[]'
+        (
+            Filter::root().attr("name").iterate(),
+            vec!["var.prefix", "var.suffix"],
+        ),
+        // filter '.ids.*'
+        (
+            Filter::root().attr("ids").wildcard(),
+            vec!["var.things", "k", "v.id", "v.enabled"],
+        ),
+        // filter '..Name'
+        (
+            Filter::Path(vec![Segment::Recursive(Field::new("Name"))]),
+            vec!["\"logs\""],
+        ),
+    ];
+
+    for (filter, expected) in cases {
+        let results: Vec<_> = query(&filter, &body)
+            .expect("query error")
+            .iter()
+            .map(|r| r.to_string().unwrap())
+            .collect();
+        assert_eq!(expected, results, "{filter}");
+    }
+}
+
+#[test]
+fn kinds() {
+    let body: hcl::Body = hcl::from_str(
+        r#"
+        resource "a" "x" {
+          lifecycle = { ignore_changes = ["tags"] }
+        }
+        resource "a" "y" {
+          lifecycle {
+            prevent_destroy = true
+          }
+        }
+        "#,
+    )
+    .expect("hcl error");
+
+    let cases = [
+        // filter '.resource.lifecycle:block'
+        (
+            Filter::root()
+                .attr("resource")
+                .attr("lifecycle")
+                .kind(Kind::Block),
+            vec!["lifecycle {\n  prevent_destroy = true\n}\n"],
+        ),
+        // filter '.resource.lifecycle:attr.*:list'
+        (
+            Filter::root()
+                .attr("resource")
+                .attr("lifecycle")
+                .kind(Kind::Attr)
+                .wildcard()
+                .kind(Kind::List),
+            vec!["[\n  \"tags\"\n]"],
+        ),
+        // filter '.resource.lifecycle:object | keys'
+        (
+            Filter::root()
+                .attr("resource")
+                .attr("lifecycle")
+                .kind(Kind::Object)
+                .pipe(Filter::Builtin(Builtin::Keys)),
+            vec!["[\n  \"ignore_changes\"\n]"],
+        ),
+    ];
+    for (filter, expected) in cases {
+        let results: Vec<_> = query(&filter, &body)
+            .expect("query error")
+            .iter()
+            .map(|r| r.to_string().unwrap())
+            .collect();
+        assert_eq!(expected, results, "{filter}");
+    }
+
+    // filter '.resource{"a", "x"}.lifecycle:block'
+    let filter = Filter::root()
+        .block("resource", ["a", "x"])
+        .attr("lifecycle")
+        .kind(Kind::Block);
+    let err = query(&filter, &body).expect_err("there is no such block");
+    assert_eq!(
+        "`.lifecycle:block` does not exist (mark it as optional with `?`, e.g. `.lifecycle:block?`)",
+        err.to_string()
+    );
+}
+
+#[test]
+fn parent_and_ancestors() {
+    let body: hcl::Body = hcl::from_str(
+        r#"
+        resource "aws_s3_bucket" "logs" {
+          versioning {
+            enabled = false
+          }
+        }
+        resource "aws_s3_bucket" "site" {
+          versioning {
+            enabled = true
+          }
+        }
+        "#,
+    )
+    .expect("hcl error");
+
+    let cases = [
+        // filter '..versioning.select(.enabled == false) | parent | labels'
+        (
+            Filter::root()
+                .recursive("versioning")
+                .select(
+                    Filter::root()
+                        .attr("enabled")
+                        .compare(Comparison::Eq, Filter::literal(false)),
+                )
+                .pipe(Builtin::Parent)
+                .pipe(Builtin::Labels),
+            vec!["[\n  \"aws_s3_bucket\",\n  \"logs\"\n]"],
+        ),
+        // filter '..enabled.select(parent | parent | labels contains "site")'
+        (
+            Filter::root().recursive("enabled").select(
+                Filter::Builtin(Builtin::Parent)
+                    .pipe(Builtin::Parent)
+                    .pipe(Builtin::Labels)
+                    .compare(Comparison::Contains, Filter::literal("site")),
+            ),
+            vec!["true"],
+        ),
+        // filter '.resource{"aws_s3_bucket", "logs"}.versioning.enabled | ancestors | type'
+        (
+            Filter::root()
+                .block("resource", ["aws_s3_bucket", "logs"])
+                .attr("versioning")
+                .attr("enabled")
+                .pipe(Builtin::Ancestors)
+                .pipe(Builtin::Type),
+            vec!["\"block\"", "\"block\"", "\"body\""],
+        ),
+        // the document and computed results have no parent
+        // filter '., length | parent'
+        (
+            Filter::root().comma(Builtin::Length).pipe(Builtin::Parent),
+            vec![],
+        ),
+    ];
+    for (filter, expected) in cases {
+        let results: Vec<_> = query(&filter, &body)
+            .expect("query error")
+            .iter()
+            .map(|r| r.to_string().unwrap())
+            .collect();
+        assert_eq!(expected, results, "{filter}");
+    }
+
+    // an enclosing result is where its input is, minus the last segments
+    // filter '.resource{"aws_s3_bucket", "logs"}.versioning.enabled | parent'
+    let filter = Filter::root()
+        .block("resource", ["aws_s3_bucket", "logs"])
+        .attr("versioning")
+        .attr("enabled")
+        .pipe(Builtin::Parent);
+    let steps = trace(&filter, &body).expect("trace error");
+    let location = steps.last().unwrap().results[0].location.clone().unwrap();
+    assert_eq!(
+        ".resource{\"aws_s3_bucket\", \"logs\"}.versioning",
+        Filter::Path(location).to_string()
+    );
+}
+
+#[test]
+fn collections() {
+    let body: hcl::Body = hcl::from_str(
+        r#"
+        ports = [443, 80, 8080, 80]
+        module "b" { source = "./b" }
+        module "a" { source = "./a" }
+        module "c" { source = "./a" }
+        "#,
+    )
+    .expect("hcl error");
+
+    let ports = || Filter::root().attr("ports");
+    let modules = || Filter::Array(Box::new(Filter::root().attr("module")));
+    let cases = [
+        // filter '.ports | sort'
+        (
+            ports().pipe(Builtin::Sort),
+            vec!["[\n  80,\n  80,\n  443,\n  8080\n]"],
+        ),
+        // filter '.ports | unique'
+        (
+            ports().pipe(Builtin::Unique),
+            vec!["[\n  80,\n  443,\n  8080\n]"],
+        ),
+        // filter '.ports | min, max, add'
+        (
+            ports().pipe(
+                Filter::Builtin(Builtin::Min)
+                    .comma(Builtin::Max)
+                    .comma(Builtin::Add),
+            ),
+            vec!["80", "8080", "8683"],
+        ),
+        // filter '.ports | map(. > 100)'
+        (
+            ports().pipe(Builtin::Map(Box::new(
+                Filter::root().compare(Comparison::Gt, Filter::literal(100)),
+            ))),
+            vec!["[\n  true,\n  false,\n  true,\n  false\n]"],
+        ),
+        // filter '.ports | group_by(. > 100)'
+        (
+            ports().pipe(Builtin::GroupBy(Box::new(
+                Filter::root().compare(Comparison::Gt, Filter::literal(100)),
+            ))),
+            vec!["[\n  [\n    80,\n    80\n  ],\n  [\n    443,\n    8080\n  ]\n]"],
+        ),
+        // an empty list has no smallest element
+        // filter '.ports[4:] | min'
+        (
+            ports().slice(Some(4), None).pipe(Builtin::Min),
+            vec!["null"],
+        ),
+        // each input is a collection on its own
+        // filter '.ports, .ports | max'
+        (
+            ports().comma(ports()).pipe(Builtin::Max),
+            vec!["8080", "8080"],
+        ),
+        // blocks are collected into a body
+        // filter '[.module] | sort_by(.source) | .* | labels'
+        (
+            modules()
+                .pipe(Builtin::SortBy(Box::new(Filter::root().attr("source"))))
+                .pipe(Filter::root().wildcard())
+                .pipe(Builtin::Labels),
+            vec!["[\n  \"a\"\n]", "[\n  \"c\"\n]", "[\n  \"b\"\n]"],
+        ),
+        // filter '[.module.source] | unique'
+        (
+            Filter::Array(Box::new(Filter::root().attr("module").attr("source")))
+                .pipe(Builtin::Unique),
+            vec!["[\n  \"./a\",\n  \"./b\"\n]"],
+        ),
+        // a group of blocks is a body, which cannot be part of a list
+        // filter '[.module] | group_by(.source) | length'
+        (
+            modules()
+                .pipe(Builtin::GroupBy(Box::new(Filter::root().attr("source"))))
+                .pipe(Builtin::Length),
+            vec!["2", "1"],
+        ),
+        // filter '[.module] | min | labels'
+        (
+            modules().pipe(Builtin::Min).pipe(Builtin::Labels),
+            vec!["[\n  \"a\"\n]"],
+        ),
+    ];
+    for (filter, expected) in cases {
+        let results: Vec<_> = query(&filter, &body)
+            .expect("query error")
+            .iter()
+            .map(|r| r.to_string().unwrap())
+            .collect();
+        assert_eq!(expected, results, "{filter}");
+    }
+
+    // filter '[.module] | map(parent) | add'
+    let filter = modules()
+        .pipe(Builtin::Map(Box::new(Filter::Builtin(Builtin::Parent))))
+        .pipe(Builtin::Add);
+    let err = query(&filter, &body).expect_err("a document cannot be added");
+    assert!(err.to_string().contains("body"), "{err}");
+
+    // neither a block nor an element of a list is a collection
+    // filter '.module | sort'
+    let filter = Filter::root().attr("module").pipe(Builtin::Sort);
+    let err = query(&filter, &body).expect_err("a block is not a collection");
+    assert_eq!("`sort` cannot be applied to block", err.to_string());
+    // filter '.ports[] | min'
+    let filter = ports().iterate().pipe(Builtin::Min);
+    let err = query(&filter, &body).expect_err("a number is not a collection");
+    assert_eq!("`min` cannot be applied to number", err.to_string());
+}
+
+#[test]
+fn strings() {
+    let body: hcl::Body = hcl::from_str(
+        r#"
+        module "vpc" { source = "git::https://github.com/org/vpc.git?ref=v1.2.3" }
+        module "local" { source = "./local" }
+        tags = ["A", "b", 1, true, null]
+        "#,
+    )
+    .expect("hcl error");
+
+    let source = || Filter::root().attr("module").attr("source");
+    let ref_pattern = Pattern::regex("ref=(?<ref>.+)$", false).unwrap();
+    let cases = [
+        // filter '.module.source | capture("ref=(?<ref>.+)$") | .ref'
+        (
+            source()
+                .pipe(Builtin::Capture(ref_pattern.clone()))
+                .pipe(Filter::root().attr("ref")),
+            vec!["\"v1.2.3\""],
+        ),
+        // filter '.module.source | test("ref=(?<ref>.+)$")'
+        (
+            source().pipe(Builtin::Test(ref_pattern)),
+            vec!["true", "false"],
+        ),
+        // filter '.module.source | startswith("git::"), endswith("local")'
+        (
+            source().pipe(
+                Filter::Builtin(Builtin::StartsWith(String::from("git::")))
+                    .comma(Builtin::EndsWith(String::from("local"))),
+            ),
+            vec!["true", "false", "false", "true"],
+        ),
+        // filter '.module.source | ltrimstr("git::") | split("/") | [-1]'
+        (
+            source()
+                .pipe(Builtin::LtrimStr(String::from("git::")))
+                .pipe(Builtin::Split(String::from("/")))
+                .pipe(Filter::root().index(-1)),
+            vec!["\"vpc.git?ref=v1.2.3\"", "\"local\""],
+        ),
+        // filter '.tags | join("-") | ascii_downcase'
+        (
+            Filter::root()
+                .attr("tags")
+                .pipe(Builtin::Join(String::from("-")))
+                .pipe(Builtin::AsciiDowncase),
+            vec!["\"a-b-1-true-\""],
+        ),
+        // filter '.module | "\(labels | [0]) uses \(.source)"'
+        (
+            Filter::root().attr("module").pipe(Filter::Template(vec![
+                TemplatePart::Interpolation(
+                    Filter::Builtin(Builtin::Labels).pipe(Filter::root().index(0)),
+                ),
+                TemplatePart::Text(String::from(" uses ")),
+                TemplatePart::Interpolation(Filter::root().attr("source")),
+            ])),
+            vec![
+                "\"vpc uses git::https://github.com/org/vpc.git?ref=v1.2.3\"",
+                "\"local uses ./local\"",
+            ],
+        ),
+        // every combination of results, and values other than strings are
+        // formatted
+        // filter '"\(1, 2)\(.tags[1:3])"'
+        (
+            Filter::Template(vec![
+                TemplatePart::Interpolation(Filter::literal(1).comma(Filter::literal(2))),
+                TemplatePart::Interpolation(Filter::root().attr("tags").slice(Some(1), Some(3))),
+            ]),
+            vec!["\"1[\\\"b\\\", 1]\"", "\"2[\\\"b\\\", 1]\""],
+        ),
+        // filter '.tags[] | ltrimstr("A")' (which keeps what is not a string)
+        (
+            Filter::root()
+                .attr("tags")
+                .iterate()
+                .pipe(Builtin::LtrimStr(String::from("A"))),
+            vec!["\"\"", "\"b\"", "1", "true", "null"],
+        ),
+    ];
+    for (filter, expected) in cases {
+        let results: Vec<_> = query(&filter, &body)
+            .expect("query error")
+            .iter()
+            .map(|r| r.to_string().unwrap())
+            .collect();
+        assert_eq!(expected, results, "{filter}");
+    }
+
+    // filter '.module | "\(.)"'
+    let filter =
+        Filter::root()
+            .attr("module")
+            .pipe(Filter::Template(vec![TemplatePart::Interpolation(
+                Filter::root(),
+            )]));
+    let err = query(&filter, &body).expect_err("a block is not a string");
+    assert_eq!(
+        "a block cannot be interpolated into a string",
+        err.to_string()
+    );
+
+    // filter '.tags[2] | split("x")'
+    let filter = Filter::root()
+        .attr("tags")
+        .index(2)
+        .pipe(Builtin::Split(String::from("x")));
+    let err = query(&filter, &body).expect_err("a number is not a string");
+    assert_eq!(
+        "`split(\"x\")` cannot be applied to number",
+        err.to_string()
+    );
+}
+
+#[test]
+fn construction() {
+    let body = utilities::read_test_hcl().expect("hcl error");
+
+    let cases = [
+        // filter '[.variable.default]'
+        (
+            Filter::Array(Box::new(Filter::root().attr("variable").attr("default"))),
+            vec!["[\n  \"my_default_value\",\n  \"another_default_value\"\n]"],
+        ),
+        // filter '.variable | {name: (labels | [0]), default}'
+        (
+            Filter::root().attr("variable").pipe(Filter::Object(vec![
+                (
+                    String::from("name"),
+                    Filter::Builtin(Builtin::Labels).pipe(Filter::root().index(0)),
+                ),
+                (String::from("default"), Filter::root().attr("default")),
+            ])),
+            vec![
+                "{\n  name = \"my_var\"\n  default = \"my_default_value\"\n}",
+                "{\n  name = \"another_var\"\n  default = \"another_default_value\"\n}",
+            ],
+        ),
+        // an object for each combination of results
+        // filter '{"a key": (1, 2), b: .version}'
+        (
+            Filter::Object(vec![
+                (
+                    String::from("a key"),
+                    Filter::literal(1).comma(Filter::literal(2)),
+                ),
+                (String::from("b"), Filter::root().attr("version")),
+            ]),
+            vec![
+                "{\n  \"a key\" = 1\n  b = \"test\"\n}",
+                "{\n  \"a key\" = 2\n  b = \"test\"\n}",
+            ],
+        ),
+        // filter '[.ports[] | . * 2] | add'
+        (
+            Filter::Array(Box::new(Filter::root().attr("ports").iterate().pipe(
+                Filter::root().arithmetic(Operator::Multiply, Filter::literal(2)),
+            )))
+            .pipe(Builtin::Add),
+            vec!["17206"],
+        ),
+    ];
+    for (filter, expected) in cases {
+        let results: Vec<_> = query(&filter, &body)
+            .expect("query error")
+            .iter()
+            .map(|r| r.to_string().unwrap())
+            .collect();
+        assert_eq!(expected, results, "{filter}");
+    }
+
+    // filter '{data: .data}'
+    let filter = Filter::Object(vec![(String::from("data"), Filter::root().attr("data"))]);
+    let err = query(&filter, &body).expect_err("a block is not a value");
+    assert_eq!("the value of `data` cannot be a block", err.to_string());
+}
@@ -3,6 +3,11 @@ options = {
     verbose = true
     debug = false
 }
+ports = [80, 443, 8080]
+ingress = [
+    { from_port = 80 },
+    { from_port = 443 },
+]
 
 variable "my_var" { default = "my_default_value" }
 variable "another_var" { default = "another_default_value" }
@@ -1,15 +1,23 @@
-use hq_rs::{parser::Field, write};
+use hcl::Value;
+use hq_rs::{
+    filter::pattern::Pattern,
+    parser::{
+        parse_filter_with_variables, Comparison, Field, Filter, Kind, Label, Predicate, Segment,
+        Variables,
+    },
+    write,
+};
 
 #[test]
 fn attr() {
     // filter '.version'
-    let fields = vec![Field::new("version")];
+    let filter = Filter::Path(vec![Field::new("version").into()]);
 
     let mut body = utilities::edit_hcl("version = \"test\"").expect("hcl error");
 
     let value: hcl_edit::expr::Expression = "\"new_value\"".parse().expect("parse error");
 
-    write(fields, &mut body, &value);
+    write(&filter, &mut body, &value).expect("write error");
 
     assert_eq!("version = \"new_value\"", body.to_string());
 }
@@ -17,13 +25,16 @@ fn attr() {
 #[test]
 fn block_attr() {
     // filter '.options.enabled'
-    let fields = vec![Field::new("options"), Field::new("enabled")];
+    let filter = Filter::Path(vec![
+        Field::new("options").into(),
+        Field::new("enabled").into(),
+    ]);
 
     let mut body = utilities::edit_hcl("options { enabled = false }").expect("hcl error");
 
     let value: hcl_edit::expr::Expression = "true".parse().expect("parse error");
 
-    write(fields, &mut body, &value);
+    write(&filter, &mut body, &value).expect("write error");
 
     assert_eq!("options { enabled = true }", body.to_string());
 }
@@ -31,17 +42,17 @@ fn block_attr() {
 #[test]
 fn labeled_block_attr() {
     // filter '.module{"cool-module"}.version'
-    let fields = vec![
-        Field::labeled("module", &["cool-module"]),
-        Field::new("version"),
-    ];
+    let filter = Filter::Path(vec![
+        Field::labeled("module", &["cool-module"]).into(),
+        Field::new("version").into(),
+    ]);
 
     let mut body =
         utilities::edit_hcl("module \"cool-module\" { version = \"1.0\" }").expect("hcl error");
 
     let value: hcl_edit::expr::Expression = "\"2.0\"".parse().expect("parse error");
 
-    write(fields, &mut body, &value);
+    write(&filter, &mut body, &value).expect("write error");
 
     assert_eq!(
         "module \"cool-module\" { version = \"2.0\" }",
@@ -52,16 +63,488 @@ fn labeled_block_attr() {
 #[test]
 fn insert() {
     // filter '.options.new_attr'
-    let fields = vec![Field::new("options"), Field::new("new_attr")];
+    let filter = Filter::Path(vec![
+        Field::new("options").into(),
+        Field::new("new_attr").into(),
+    ]);
 
     let mut body = utilities::edit_hcl("options { attr = \"value\" }").expect("hcl error");
 
     let value: hcl_edit::expr::Expression = "\"new_value\"".parse().expect("parse error");
 
-    write(fields, &mut body, &value);
+    write(&filter, &mut body, &value).expect("write error");
 
     assert_eq!(
         "options {\n attr = \"value\" \n new_attr = \"new_value\" \n}",
         body.to_string()
     );
 }
+
+#[test]
+fn array_element() {
+    // filter '.ports[-1]'
+    let filter = Filter::Path(vec![Field::new("ports").into(), Segment::Index(-1)]);
+
+    let mut body = utilities::edit_hcl("ports = [80, 443]").expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "8443".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!("ports = [80, 8443]", body.to_string());
+}
+
+#[test]
+fn array_element_out_of_range() {
+    let value: hcl_edit::expr::Expression = "1".parse().expect("parse error");
+
+    for index in [5, -9] {
+        // filter '.ports[5]' and '.ports[-9]'
+        let filter = Filter::Path(vec![Field::new("ports").into(), Segment::Index(index)]);
+
+        let mut body = utilities::edit_hcl("ports = [80, 443]").expect("hcl error");
+
+        let err = write(&filter, &mut body, &value).expect_err("out of range");
+
+        assert_eq!(
+            format!("`[{index}]` does not exist (mark it as optional with `?`, e.g. `[{index}]?`)"),
+            err.to_string()
+        );
+        assert_eq!("ports = [80, 443]", body.to_string());
+    }
+
+    // filter '.ports[5]?'
+    let filter = Filter::Path(vec![
+        Field::new("ports").into(),
+        Segment::Index(5),
+        Segment::Optional,
+    ]);
+
+    let mut body = utilities::edit_hcl("ports = [80, 443]").expect("hcl error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!("ports = [80, 443]", body.to_string());
+}
+
+#[test]
+fn array_element_object_attr() {
+    // filter '.ingress[0].from_port'
+    let filter = Filter::Path(vec![
+        Field::new("ingress").into(),
+        Segment::Index(0),
+        Field::new("from_port").into(),
+    ]);
+
+    let mut body = utilities::edit_hcl("ingress = [{ from_port = 80 }]").expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "8080".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!("ingress = [{ from_port = 8080 }]", body.to_string());
+}
+
+#[test]
+fn array_slice() {
+    // filter '.ports[1:]'
+    let filter = Filter::Path(vec![
+        Field::new("ports").into(),
+        Segment::Slice(Some(1), None),
+    ]);
+
+    let mut body = utilities::edit_hcl("ports = [80, 443, 8080]").expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "[8443]".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!("ports = [80, 8443]", body.to_string());
+}
+
+#[test]
+fn wildcard() {
+    // filter '.options.*'
+    let filter = Filter::Path(vec![Field::new("options").into(), Segment::Wildcard]);
+
+    let mut body =
+        utilities::edit_hcl("options {\n  debug = false\n  verbose = false\n}").expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "true".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "options {\n  debug = true\n  verbose = true\n}",
+        body.to_string()
+    );
+}
+
+#[test]
+fn wildcard_labels() {
+    // `.*` steps over the label that `.variable` does not name
+    // filter '.variable.*.default'
+    let filter = Filter::Path(vec![
+        Field::new("variable").into(),
+        Segment::Wildcard,
+        Field::new("default").into(),
+    ]);
+
+    let mut body = utilities::edit_hcl(
+        "variable \"a\" {\n  default = 1\n}\nvariable \"b\" {\n  default = 2\n}",
+    )
+    .expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "0".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "variable \"a\" {\n  default = 0\n}\nvariable \"b\" {\n  default = 0\n}",
+        body.to_string()
+    );
+
+    // filter '.variable.*', which is each of the blocks
+    let filter = Filter::Path(vec![Field::new("variable").into(), Segment::Wildcard]);
+    let err = write(&filter, &mut body, &value).expect_err("a block cannot be written");
+    assert!(err.to_string().contains("is a block"), "{err}");
+}
+
+#[test]
+fn iterate() {
+    // filter '.ingress[].from_port'
+    let filter = Filter::Path(vec![
+        Field::new("ingress").into(),
+        Segment::Iterate,
+        Field::new("from_port").into(),
+    ]);
+
+    let mut body = utilities::edit_hcl("ingress = [{ from_port = 80 }, { from_port = 443 }]")
+        .expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "0".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "ingress = [{ from_port = 0 }, { from_port = 0 }]",
+        body.to_string()
+    );
+}
+
+#[test]
+fn positional_labeled_block_attr() {
+    // filter '.data{"aws_ami","ubuntu"}.most_recent'
+    let filter = Filter::Path(vec![
+        Field::labeled("data", &["aws_ami", "ubuntu"]).into(),
+        Field::new("most_recent").into(),
+    ]);
+
+    let mut body = utilities::edit_hcl(
+        "data \"aws_ami\" \"ubuntu\" { most_recent = false }\ndata \"aws_ami\" \"other\" { most_recent = false }\n",
+    )
+    .expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "true".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "data \"aws_ami\" \"ubuntu\" { most_recent = true }\ndata \"aws_ami\" \"other\" { most_recent = false }\n",
+        body.to_string()
+    );
+}
+
+#[test]
+fn select() {
+    // filter '.resource.select(.instance_type == "t2.micro").instance_type'
+    let filter = Filter::Path(vec![
+        Field::new("resource").into(),
+        Segment::Select(Predicate::from(
+            Filter::Path(vec![Field::new("instance_type").into()])
+                .compare(Comparison::Eq, Filter::literal(Value::from("t2.micro"))),
+        )),
+        Field::new("instance_type").into(),
+    ]);
+
+    let mut body = utilities::edit_hcl(
+        "resource \"aws_instance\" \"a\" { instance_type = \"t2.micro\" }\nresource \"aws_instance\" \"b\" { instance_type = \"m5.large\" }\n",
+    )
+    .expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "\"t3.micro\"".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "resource \"aws_instance\" \"a\" { instance_type = \"t3.micro\" }\nresource \"aws_instance\" \"b\" { instance_type = \"m5.large\" }\n",
+        body.to_string()
+    );
+}
+
+#[test]
+fn pipe_comma() {
+    // filter '.options | .verbose, .debug'
+    let filter = Filter::Pipe(vec![
+        Filter::Path(vec![Field::new("options").into()]),
+        Filter::Comma(vec![
+            Filter::Path(vec![Field::new("verbose").into()]),
+            Filter::Path(vec![Field::new("debug").into()]),
+        ]),
+    ]);
+
+    let mut body = utilities::edit_hcl("options {\n  verbose = false\n  debug = false\n}\n")
+        .expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "true".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "options {\n  verbose = true\n  debug = true\n}\n",
+        body.to_string()
+    );
+}
+
+#[test]
+fn glob_labeled_block_attr() {
+    // filter '.module{"prod-*"}.version'
+    let filter = Filter::Path(vec![
+        Field {
+            labels: vec![Label::Pattern(Pattern::glob("prod-*", false))],
+            ..Field::new("module")
+        }
+        .into(),
+        Field::new("version").into(),
+    ]);
+
+    let mut body = utilities::edit_hcl(
+        "module \"prod-a\" { version = \"1\" }\nmodule \"dev-a\" { version = \"1\" }\nmodule \"prod-b\" { version = \"1\" }\n",
+    )
+    .expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "\"2\"".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "module \"prod-a\" { version = \"2\" }\nmodule \"dev-a\" { version = \"1\" }\nmodule \"prod-b\" { version = \"2\" }\n",
+        body.to_string()
+    );
+}
+
+#[test]
+fn pattern_does_not_insert() {
+    // filter '.options.debug_*'
+    let filter = Filter::Path(vec![
+        Field::new("options").into(),
+        Field {
+            pattern: Some(Pattern::glob("debug_*", false)),
+            ..Field::new("debug_*")
+        }
+        .into(),
+    ]);
+
+    let mut body = utilities::edit_hcl("options { enabled = false }").expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "true".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!("options { enabled = false }", body.to_string());
+}
+
+#[test]
+fn optional_segment() {
+    // filter '.options?.enabled'
+    let filter = Filter::Path(vec![
+        Field::new("options").into(),
+        Segment::Optional,
+        Field::new("enabled").into(),
+    ]);
+
+    let mut body = utilities::edit_hcl("options { enabled = false }").expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "true".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!("options { enabled = true }", body.to_string());
+}
+
+#[test]
+fn missing_field() {
+    let mut body = utilities::edit_hcl("variable \"a\" {}").expect("hcl error");
+    let value: hcl_edit::expr::Expression = "1".parse().expect("parse error");
+
+    // only the last field of a path is added when it is missing
+    let cases = [
+        // filter '.missing.x'
+        (
+            vec![Field::new("missing").into(), Field::new("x").into()],
+            "`.missing` does not exist (mark it as optional with `?`, e.g. `.missing?`)",
+        ),
+        // filter '.variable{"nope"}.default'
+        (
+            vec![
+                Field::labeled("variable", &["nope"]).into(),
+                Field::new("default").into(),
+            ],
+            "`.variable{\"nope\"}` does not exist (mark it as optional with `?`, e.g. `.variable{\"nope\"}?`)",
+        ),
+    ];
+    for (segments, expected) in cases {
+        let filter = Filter::Path(segments);
+        let err = write(&filter, &mut body, &value).expect_err("missing field");
+        assert_eq!(expected, err.to_string(), "{filter}");
+    }
+
+    // filter '.missing?.x'
+    let filter = Filter::Path(vec![
+        Field::new("missing").into(),
+        Segment::Optional,
+        Field::new("x").into(),
+    ]);
+    write(&filter, &mut body, &value).expect("write error");
+    assert_eq!("variable \"a\" {}", body.to_string());
+}
+
+#[test]
+fn built_filter() {
+    // filter '.resource{"aws_s3_bucket", "logs"}.tags[0]'
+    let filter = Filter::root()
+        .block("resource", ["aws_s3_bucket", "logs"])
+        .attr("tags")
+        .index(0);
+
+    let mut body = utilities::edit_hcl("resource \"aws_s3_bucket\" \"logs\" { tags = [\"a\"] }")
+        .expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "\"b\"".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "resource \"aws_s3_bucket\" \"logs\" { tags = [\"b\"] }",
+        body.to_string()
+    );
+}
+
+#[test]
+fn variable_label() {
+    // filter '.module{$name}.version' with $name = 'say "hi"'
+    let variables = Variables::from([(String::from("name"), Value::from("say \"hi\""))]);
+    let filter =
+        parse_filter_with_variables(".module{$name}.version", &variables).expect("parse error");
+
+    let mut body =
+        utilities::edit_hcl("module \"say \\\"hi\\\"\" { version = \"1\" }").expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "\"2\"".parse().expect("parse error");
+
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "module \"say \\\"hi\\\"\" { version = \"2\" }",
+        body.to_string()
+    );
+}
+
+#[test]
+fn expression_parts() {
+    let mut body = utilities::edit_hcl(
+        "tags = merge(local.tags, { Name = \"logs\" })\nsize = var.prod ? \"large\" : \"small\"\n",
+    )
+    .expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "\"new\"".parse().expect("parse error");
+
+    // filter '.tags[1].Name, .size.false'
+    let filter = Filter::root()
+        .attr("tags")
+        .index(1)
+        .attr("Name")
+        .comma(Filter::root().attr("size").attr("false"));
+    write(&filter, &mut body, &value).expect("write error");
+
+    assert_eq!(
+        "tags = merge(local.tags, { Name = \"new\" })\nsize = var.prod ? \"large\" : \"new\"\n",
+        body.to_string()
+    );
+}
+
+#[test]
+fn kinds() {
+    let mut body = utilities::edit_hcl(
+        "lifecycle = { prevent_destroy = false }\nlifecycle {\n  prevent_destroy = false\n}\n",
+    )
+    .expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "true".parse().expect("parse error");
+
+    // filter '.lifecycle:block.prevent_destroy'
+    let filter = Filter::root()
+        .attr("lifecycle")
+        .kind(Kind::Block)
+        .attr("prevent_destroy");
+    write(&filter, &mut body, &value).expect("write error");
+
+    // filter '.enabled:attr' (which is added, since it is an attribute)
+    let filter = Filter::root().attr("enabled").kind(Kind::Attr);
+    write(&filter, &mut body, &value).expect("write error");
+
+    // filter '.missing:block' (which cannot be added)
+    let filter = Filter::root().attr("missing").kind(Kind::Block);
+    let err = write(&filter, &mut body, &value).expect_err("a value is not a block");
+    assert_eq!(
+        "cannot write to `.missing:block`, since the value is not of kind `:block`",
+        err.to_string()
+    );
+
+    assert_eq!(
+        "lifecycle = { prevent_destroy = false }\nlifecycle {\n  prevent_destroy = true\n}\nenabled = true\n",
+        body.to_string()
+    );
+}
+
+#[test]
+fn block_with_same_name() {
+    let hcl = "resource \"a\" \"b\" {\n  lifecycle {\n    prevent_destroy = false\n  }\n}\n";
+    let mut body = utilities::edit_hcl(hcl).expect("hcl error");
+
+    let value: hcl_edit::expr::Expression = "true".parse().expect("parse error");
+
+    let lifecycle = || Filter::root().attr("resource").attr("lifecycle");
+    let cases = [
+        // filter '.resource.lifecycle'
+        (
+            lifecycle(),
+            "`.lifecycle` is a block, which cannot be replaced with a value",
+        ),
+        // filter '.resource.lifecycle:block'
+        (
+            lifecycle().kind(Kind::Block),
+            "`.lifecycle:block` is a block, which cannot be replaced with a value",
+        ),
+        // filter '.resource.lifecycle:attr'
+        (
+            lifecycle().kind(Kind::Attr),
+            "cannot add `.lifecycle:attr`, since there is a block with the same name",
+        ),
+        // filter '.resource.tags:list'
+        (
+            Filter::root()
+                .attr("resource")
+                .attr("tags")
+                .kind(Kind::List),
+            "cannot write to `.tags:list`, since the value is not of kind `:list`",
+        ),
+    ];
+
+    for (filter, expected) in cases {
+        let err = write(&filter, &mut body, &value).expect_err("write should fail");
+        assert_eq!(expected, err.to_string(), "{filter}");
+    }
+
+    assert_eq!(hcl, body.to_string());
+}